@@ -15,4 +15,5 @@ impl Foo for String {
 
 fn main() {
     BoxedFoo::new("Hello World!".to_string()).fooify("Another string!");
+    BoxedFoo::new_with(|| "Hello in place!".to_string()).fooify("Yet another string!");
 }
@@ -0,0 +1,23 @@
+use std::os::raw::c_int;
+use thin_trait_object::*;
+
+#[thin_trait_object(abi = "C")]
+trait Accumulate: FnMut(c_int) -> c_int {}
+
+#[thin_trait_object(abi = "C")]
+trait Finish: FnOnce() -> usize {}
+
+fn main() {
+    let mut total = 0;
+    let mut accumulate = BoxedAccumulate::new(move |x| {
+        total += x;
+        total
+    });
+    accumulate.call_mut(1);
+    assert_eq!(accumulate.call_mut(2), 3);
+
+    let name = String::from("finish");
+    let finish = BoxedFinish::new(move || name.len());
+    assert_eq!(std::mem::size_of_val(&finish), std::mem::size_of::<usize>());
+    assert_eq!(finish.call_once(), 6);
+}
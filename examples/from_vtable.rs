@@ -0,0 +1,28 @@
+use std::os::raw::{c_int, c_void};
+use thin_trait_object::*;
+
+// This is what bindgen generates for
+// struct greeter_ops {
+//     void (*greet)(void* self, int times);
+//     void (*destroy)(void* self);
+// };
+#[thin_trait_object_from_vtable(drop_slot = destroy, trait_def(pub Greeter), strict_ffi = true)]
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct greeter_ops {
+    pub greet: Option<unsafe extern "C" fn(this: *mut c_void, times: c_int)>,
+    pub destroy: Option<unsafe extern "C" fn(this: *mut c_void)>,
+}
+
+impl Greeter for String {
+    extern "C" fn greet(&mut self, times: c_int) {
+        for _ in 0..times {
+            println!("Hello from \"{}\"", self);
+        }
+    }
+}
+
+fn main() {
+    let mut greeter = BoxedGreeter::new("Hello World!".to_string());
+    greeter.greet(2);
+}
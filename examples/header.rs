@@ -0,0 +1,28 @@
+use thin_trait_object::*;
+
+// Mirrors `struct file { int refs; const struct file_ops *ops; }` on the C side.
+#[repr(C)]
+#[derive(Default)]
+struct FileHeader {
+    refs: i32,
+}
+
+#[thin_trait_object(header(FileHeader))]
+trait File {
+    fn size(&self) -> u64;
+}
+impl File for Vec<u8> {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+fn main() {
+    let mut file = BoxedFile::new_with_header(FileHeader { refs: 1 }, vec![0; 1024]);
+    file.header_mut().refs += 1;
+    assert_eq!(file.header().refs, 2);
+    assert_eq!(file.size(), 1024);
+    let defaulted = BoxedFile::new(Vec::new());
+    assert_eq!(defaulted.header().refs, 0);
+    assert_eq!(defaulted.size(), 0);
+}
@@ -13,6 +13,8 @@ trait Foo: SafeMarker + UnsafeMarker {
 }
 
 trait SafeMarker {}
+/// # Safety
+/// Implementing this marker has no requirements, it only demonstrates unsafe marker traits.
 unsafe trait UnsafeMarker {}
 
 impl Foo for String {
@@ -0,0 +1,217 @@
+//! Thin closures which are generic over their argument and return types.
+
+use crate::{raw, ThinTraitObject};
+use std::{ffi::c_void, marker::PhantomData, ptr::NonNull};
+
+/// The argument lists which thin closures can take, implemented for tuples of up to 6 elements.
+pub trait ThinArgs<R>: private::Sealed {
+    /// The type of the vtable entry which calls a closure taking these arguments and returning `R`, i.e. an `unsafe extern "C" fn` taking the pointer to the object followed by the arguments.
+    type Entry: Copy;
+}
+mod private {
+    pub trait Sealed {}
+}
+
+/// Defines a thin closure type, along with its vtable and the methods which don't depend on the number of arguments.
+macro_rules! thin_closure {
+    (
+        $(#[$attr:meta])*
+        $name:ident,
+        $vtable:ident,
+        $vtable_doc:literal,
+        $entry:ident,
+        $entry_doc:literal $(,)?
+    ) => {
+        $(#[$attr])*
+        #[repr(transparent)]
+        pub struct $name<Args: ThinArgs<R>, R>(
+            NonNull<*const $vtable<Args, R>>,
+            PhantomData<fn(Args) -> R>,
+        );
+        #[doc = $vtable_doc]
+        #[repr(C)]
+        pub struct $vtable<Args: ThinArgs<R>, R> {
+            #[doc = $entry_doc]
+            pub $entry: Args::Entry,
+            /// Drops the closure and frees the allocation.
+            pub drop: unsafe extern "C" fn(*mut c_void),
+        }
+        impl<Args: ThinArgs<R>, R> Clone for $vtable<Args, R> {
+            #[inline]
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+        impl<Args: ThinArgs<R>, R> Copy for $vtable<Args, R> {}
+        impl<Args: ThinArgs<R>, R> $name<Args, R> {
+            /// Creates a thin closure directly from a raw pointer to it.
+            ///
+            /// # Safety
+            /// The pointer must not be null and must point to a closure which can be used with the vtable which it starts with, and ownership of the closure is taken.
+            #[inline]
+            pub const unsafe fn from_raw(ptr: *mut ()) -> Self {
+                Self(NonNull::new_unchecked(ptr as *mut _), PhantomData)
+            }
+            /// Extracts the contained pointer to the closure without releasing ownership of it.
+            #[inline]
+            pub const fn as_raw(&self) -> *mut () {
+                self.0.as_ptr() as *mut ()
+            }
+            /// Releases ownership of the closure, returning the contained pointer. It is the caller's responsibility to drop the closure at a later time using [`from_raw`].
+            ///
+            /// [`from_raw`]: #method.from_raw " "
+            #[inline]
+            pub fn into_raw(self) -> *mut () {
+                let pointer = self.as_raw();
+                ::std::mem::forget(self);
+                pointer
+            }
+            /// Retrieves the raw vtable of the closure.
+            #[inline]
+            pub fn vtable(&self) -> &$vtable<Args, R> {
+                unsafe { raw::vtable(self.as_raw()) }
+            }
+        }
+        impl<Args: ThinArgs<R>, R> Drop for $name<Args, R> {
+            fn drop(&mut self) {
+                unsafe { (self.vtable().drop)(self.as_raw() as _) }
+            }
+        }
+        // The closures are required to be `Send`.
+        unsafe impl<Args: ThinArgs<R>, R> Send for $name<Args, R> {}
+        unsafe impl<Args: ThinArgs<R>, R> ThinTraitObject for $name<Args, R> {
+            type Vtable = $vtable<Args, R>;
+            #[inline]
+            unsafe fn from_raw(ptr: *mut ()) -> Self {
+                Self::from_raw(ptr)
+            }
+            #[inline]
+            fn as_raw(&self) -> *mut () {
+                Self::as_raw(self)
+            }
+            #[inline]
+            fn vtable(&self) -> &Self::Vtable {
+                Self::vtable(self)
+            }
+        }
+    };
+}
+
+thin_closure! {
+    /// A thin `Fn` closure, one pointer wide, which can be called by C code through the `call` entry of its vtable.
+    ThinFn,
+    ThinFnVtable,
+    "The vtable of [`ThinFn`].",
+    call,
+    "Calls the closure with the arguments, leaving it in place.",
+}
+thin_closure! {
+    /// A thin `FnMut` closure, one pointer wide, which can be called by C code through the `call_mut` entry of its vtable.
+    ThinFnMut,
+    ThinFnMutVtable,
+    "The vtable of [`ThinFnMut`].",
+    call_mut,
+    "Calls the closure with the arguments, leaving it in place. Must not be called again before the previous call returns.",
+}
+thin_closure! {
+    /// A thin `FnOnce` closure, one pointer wide, which can be called by C code through the `call_once` entry of its vtable.
+    ThinFnOnce,
+    ThinFnOnceVtable,
+    "The vtable of [`ThinFnOnce`].",
+    call_once,
+    "Moves the closure out of the allocation, frees it and then calls the closure with the arguments. The destructor must not be called afterwards.",
+}
+
+/// The vtables and vtable entries for a closure of type `F`.
+struct Thunks<F, Args, R>(PhantomData<(F, Args, R)>);
+
+/// Implements the thin closures for closures taking the specified arguments.
+macro_rules! impl_arity {
+    ($($arg:ident: $ty:ident),*) => {
+        impl<$($ty),*> private::Sealed for ($($ty,)*) {}
+        impl<$($ty,)* R> ThinArgs<R> for ($($ty,)*) {
+            type Entry = unsafe extern "C" fn(*mut c_void, $($ty),*) -> R;
+        }
+        impl<$($ty,)* R> ThinFn<($($ty,)*), R> {
+            /// Constructs a thin closure from a closure.
+            #[inline]
+            pub fn new<F: Fn($($ty),*) -> R + Send + 'static>(f: F) -> Self {
+                let vtable = &Thunks::<F, ($($ty,)*), R>::FN_VTABLE;
+                unsafe { Self::from_raw(raw::create(vtable, f)) }
+            }
+            /// Calls the closure.
+            #[inline]
+            #[allow(clippy::too_many_arguments)]
+            pub fn call(&self, $($arg: $ty),*) -> R {
+                unsafe { (self.vtable().call)(self.as_raw() as _, $($arg),*) }
+            }
+        }
+        impl<$($ty,)* R> ThinFnMut<($($ty,)*), R> {
+            /// Constructs a thin closure from a closure.
+            #[inline]
+            pub fn new<F: FnMut($($ty),*) -> R + Send + 'static>(f: F) -> Self {
+                let vtable = &Thunks::<F, ($($ty,)*), R>::FN_MUT_VTABLE;
+                unsafe { Self::from_raw(raw::create(vtable, f)) }
+            }
+            /// Calls the closure.
+            #[inline]
+            #[allow(clippy::too_many_arguments)]
+            pub fn call_mut(&mut self, $($arg: $ty),*) -> R {
+                unsafe { (self.vtable().call_mut)(self.as_raw() as _, $($arg),*) }
+            }
+        }
+        impl<$($ty,)* R> ThinFnOnce<($($ty,)*), R> {
+            /// Constructs a thin closure from a closure.
+            #[inline]
+            pub fn new<F: FnOnce($($ty),*) -> R + Send + 'static>(f: F) -> Self {
+                let vtable = &Thunks::<F, ($($ty,)*), R>::FN_ONCE_VTABLE;
+                unsafe { Self::from_raw(raw::create(vtable, f)) }
+            }
+            /// Calls the closure, consuming it.
+            #[inline]
+            #[allow(clippy::too_many_arguments)]
+            pub fn call_once(self, $($arg: $ty),*) -> R {
+                let call_once = self.vtable().call_once;
+                unsafe { call_once(self.into_raw() as _, $($arg),*) }
+            }
+        }
+        impl<F: Fn($($ty),*) -> R, $($ty,)* R> Thunks<F, ($($ty,)*), R> {
+            const FN_VTABLE: ThinFnVtable<($($ty,)*), R> = ThinFnVtable {
+                call: Self::call,
+                drop: raw::drop::<ThinFnVtable<($($ty,)*), R>, F>,
+            };
+            unsafe extern "C" fn call(ptr: *mut c_void, $($arg: $ty),*) -> R {
+                let f = &*raw::value::<ThinFnVtable<($($ty,)*), R>, F>(ptr);
+                raw::abort_on_panic(|| f($($arg),*))
+            }
+        }
+        impl<F: FnMut($($ty),*) -> R, $($ty,)* R> Thunks<F, ($($ty,)*), R> {
+            const FN_MUT_VTABLE: ThinFnMutVtable<($($ty,)*), R> = ThinFnMutVtable {
+                call_mut: Self::call_mut,
+                drop: raw::drop::<ThinFnMutVtable<($($ty,)*), R>, F>,
+            };
+            unsafe extern "C" fn call_mut(ptr: *mut c_void, $($arg: $ty),*) -> R {
+                let f = &mut *raw::value::<ThinFnMutVtable<($($ty,)*), R>, F>(ptr);
+                raw::abort_on_panic(|| f($($arg),*))
+            }
+        }
+        impl<F: FnOnce($($ty),*) -> R, $($ty,)* R> Thunks<F, ($($ty,)*), R> {
+            const FN_ONCE_VTABLE: ThinFnOnceVtable<($($ty,)*), R> = ThinFnOnceVtable {
+                call_once: Self::call_once,
+                drop: raw::drop::<ThinFnOnceVtable<($($ty,)*), R>, F>,
+            };
+            unsafe extern "C" fn call_once(ptr: *mut c_void, $($arg: $ty),*) -> R {
+                let f = raw::take::<ThinFnOnceVtable<($($ty,)*), R>, F>(ptr);
+                raw::abort_on_panic(|| f($($arg),*))
+            }
+        }
+    };
+}
+
+impl_arity!();
+impl_arity!(a: A);
+impl_arity!(a: A, b: B);
+impl_arity!(a: A, b: B, c: C);
+impl_arity!(a: A, b: B, c: C, d: D);
+impl_arity!(a: A, b: B, c: C, d: D, e: E);
+impl_arity!(a: A, b: B, c: C, d: D, e: E, f: G);
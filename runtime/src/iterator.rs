@@ -0,0 +1,137 @@
+//! A thin iterator which is generic over its item type.
+
+use crate::{raw, ThinTraitObject};
+use std::{ffi::c_void, marker::PhantomData, mem::MaybeUninit, ptr::NonNull};
+
+/// A thin `Iterator` trait object, one pointer wide, whose items can be retrieved by C code through its vtable.
+#[repr(transparent)]
+pub struct ThinIterator<T>(NonNull<*const ThinIteratorVtable<T>>, PhantomData<T>);
+
+/// The vtable of [`ThinIterator`].
+#[repr(C)]
+pub struct ThinIteratorVtable<T> {
+    /// Advances the iterator, writing the next item into the second argument and returning `true`, or returning `false` without writing anything if the iterator is exhausted.
+    pub next: unsafe extern "C" fn(*mut c_void, *mut T) -> bool,
+    /// Writes the lower bound of the remaining length into the second argument and the upper bound into the third one, returning whether there is an upper bound at all. The third argument is left untouched if there isn't.
+    pub size_hint: unsafe extern "C" fn(*mut c_void, *mut usize, *mut usize) -> bool,
+    /// Drops the iterator and frees the allocation.
+    pub drop: unsafe extern "C" fn(*mut c_void),
+}
+impl<T> Clone for ThinIteratorVtable<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for ThinIteratorVtable<T> {}
+
+impl<T> ThinIterator<T> {
+    /// Constructs a thin iterator from an iterator.
+    #[inline]
+    pub fn new<I: Iterator<Item = T> + Send + 'static>(iterator: I) -> Self {
+        let vtable = &Thunks::<I>::VTABLE;
+        unsafe { Self::from_raw(raw::create(vtable, iterator)) }
+    }
+    /// Creates a thin iterator directly from a raw pointer to it.
+    ///
+    /// # Safety
+    /// The pointer must not be null and must point to an iterator which can be used with the vtable which it starts with, and ownership of the iterator is taken.
+    #[inline]
+    pub const unsafe fn from_raw(ptr: *mut ()) -> Self {
+        Self(NonNull::new_unchecked(ptr as *mut _), PhantomData)
+    }
+    /// Extracts the contained pointer to the iterator without releasing ownership of it.
+    #[inline]
+    pub const fn as_raw(&self) -> *mut () {
+        self.0.as_ptr() as *mut ()
+    }
+    /// Releases ownership of the iterator, returning the contained pointer. It is the caller's responsibility to drop the iterator at a later time using [`from_raw`].
+    ///
+    /// [`from_raw`]: #method.from_raw " "
+    #[inline]
+    pub fn into_raw(self) -> *mut () {
+        let pointer = self.as_raw();
+        std::mem::forget(self);
+        pointer
+    }
+    /// Retrieves the raw vtable of the iterator.
+    #[inline]
+    pub fn vtable(&self) -> &ThinIteratorVtable<T> {
+        unsafe { raw::vtable(self.as_raw()) }
+    }
+}
+impl<T> Iterator for ThinIterator<T> {
+    type Item = T;
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        let mut item = MaybeUninit::uninit();
+        unsafe {
+            if (self.vtable().next)(self.as_raw() as _, item.as_mut_ptr()) {
+                Some(item.assume_init())
+            } else {
+                None
+            }
+        }
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (mut lower, mut upper) = (0, 0);
+        let has_upper =
+            unsafe { (self.vtable().size_hint)(self.as_raw() as _, &mut lower, &mut upper) };
+        (lower, if has_upper { Some(upper) } else { None })
+    }
+}
+impl<T> Drop for ThinIterator<T> {
+    fn drop(&mut self) {
+        unsafe { (self.vtable().drop)(self.as_raw() as _) }
+    }
+}
+// The iterators are required to be `Send`.
+unsafe impl<T> Send for ThinIterator<T> {}
+unsafe impl<T> ThinTraitObject for ThinIterator<T> {
+    type Vtable = ThinIteratorVtable<T>;
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Self {
+        Self::from_raw(ptr)
+    }
+    #[inline]
+    fn as_raw(&self) -> *mut () {
+        Self::as_raw(self)
+    }
+    #[inline]
+    fn vtable(&self) -> &Self::Vtable {
+        Self::vtable(self)
+    }
+}
+
+/// The vtable and vtable entries for an iterator of type `I`.
+struct Thunks<I>(PhantomData<I>);
+impl<I: Iterator> Thunks<I> {
+    const VTABLE: ThinIteratorVtable<I::Item> = ThinIteratorVtable {
+        next: Self::next,
+        size_hint: Self::size_hint,
+        drop: raw::drop::<ThinIteratorVtable<I::Item>, I>,
+    };
+    unsafe extern "C" fn next(ptr: *mut c_void, out: *mut I::Item) -> bool {
+        let iterator = &mut *raw::value::<ThinIteratorVtable<I::Item>, I>(ptr);
+        match raw::abort_on_panic(|| iterator.next()) {
+            Some(item) => {
+                out.write(item);
+                true
+            }
+            None => false,
+        }
+    }
+    unsafe extern "C" fn size_hint(ptr: *mut c_void, lower: *mut usize, upper: *mut usize) -> bool {
+        let iterator = &*raw::value::<ThinIteratorVtable<I::Item>, I>(ptr);
+        let (size_lower, size_upper) = raw::abort_on_panic(|| iterator.size_hint());
+        lower.write(size_lower);
+        match size_upper {
+            Some(size_upper) => {
+                upper.write(size_upper);
+                true
+            }
+            None => false,
+        }
+    }
+}
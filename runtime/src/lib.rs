@@ -0,0 +1,238 @@
+//! Ready-made thin trait objects for common standard library traits, generated with [`thin_trait_object`].
+//!
+//! Every crate using `#[thin_trait_object]` gets its own copy of the generated types, which means that two crates which want to exchange, say, a thin reader would have to agree on which one of them defines it. This crate serves as that common definition for the most widely used interfaces:
+//! - [`ThinRead`] and [`ThinWrite`], for `io::Read` and `io::Write`;
+//! - [`ThinError`], for `Error`, one pointer wide, which makes it cheap to return;
+//! - [`ThinAny`], for `Any`, which can be downcast to the type it was created from;
+//! - [`ThinIterator`], for `Iterator` with any item type;
+//! - [`ThinFn`], [`ThinFnMut`] and [`ThinFnOnce`], for closures taking up to 6 arguments.
+//!
+//! [`ThinRead`], [`ThinWrite`], [`ThinError`] and [`ThinAny`] have the `"C"` ABI in their vtables, so they can be passed to and implemented by C code, and catch panics at the FFI boundary, resuming them once the call returns to Rust. The traits which they are made of are implemented for every type implementing the respective standard library trait, so creating one only takes a call to `new`:
+//! ```rust
+//! use std::io::{Read, Write};
+//! use thin_trait_object_runtime::*;
+//!
+//! let mut output = ThinWrite::new(Vec::new());
+//! output.write_all(b"Hello World!").unwrap();
+//!
+//! let mut input = ThinRead::new(&b"Hello World!"[..]);
+//! let mut text = String::new();
+//! input.read_to_string(&mut text).unwrap();
+//! assert_eq!(text, "Hello World!");
+//!
+//! let value = ThinAny::new(42_u32);
+//! assert_eq!(value.downcast_ref::<u32>(), Some(&42));
+//! assert!(value.downcast_ref::<i32>().is_none());
+//! ```
+//!
+//! [`ThinError`] can be returned in place of `Box<dyn Error + Send + Sync>`, and converted into one with the `?` operator:
+//! ```rust
+//! use std::{error::Error, mem};
+//! use thin_trait_object_runtime::ThinError;
+//!
+//! fn parse(input: &str) -> Result<u32, ThinError> {
+//!     input.parse().map_err(ThinError::new)
+//! }
+//! fn run() -> Result<u32, Box<dyn Error + Send + Sync>> {
+//!     Ok(parse("forty-two")?)
+//! }
+//!
+//! assert_eq!(mem::size_of::<ThinError>(), mem::size_of::<usize>());
+//! assert_eq!(run().unwrap_err().to_string(), "invalid digit found in string");
+//! ```
+//!
+//! The traits can be used in the `thin_traits(...)` option of the macro, so that the thin trait objects from this crate can be passed through the vtables of other traits as pointers:
+//! ```rust
+//! use thin_trait_object::*;
+//! use thin_trait_object_runtime::{DynRead, ThinRead};
+//!
+//! #[thin_trait_object(abi = "C", thin_traits(DynRead = ThinRead))]
+//! trait Decoder {
+//!     fn decode(&self, input: ThinRead) -> u32;
+//! }
+//! ```
+//!
+//! The [`ThinTraitObject`] trait is implemented by the thin trait objects in this crate, as well as by those generated with the `runtime = thin_trait_object_runtime` option of the macro, making it possible to write code which works with any of them:
+//! ```rust
+//! use thin_trait_object::*;
+//! use thin_trait_object_runtime::ThinTraitObject;
+//!
+//! /// Passes ownership of any thin trait object to C code, which gets both the object and its vtable.
+//! fn export<T: ThinTraitObject>(object: T) -> (*mut (), *const T::Vtable) {
+//!     let vtable = object.vtable() as *const T::Vtable;
+//!     (object.into_raw(), vtable)
+//! }
+//!
+//! #[thin_trait_object(runtime = thin_trait_object_runtime)]
+//! trait Foo {
+//!     fn foo(&self) -> u32;
+//! }
+//! impl Foo for u32 {
+//!     fn foo(&self) -> u32 {
+//!         *self
+//!     }
+//! }
+//!
+//! let (object, vtable) = export(BoxedFoo::new(42));
+//! assert_eq!(unsafe { ((*vtable).foo)(object as _) }, 42);
+//! drop(unsafe { BoxedFoo::from_raw(object) });
+//! ```
+//!
+//! [`ThinIterator`] and the thin closures are generic over their item, argument and return types, which the macro cannot express, so they are written by hand instead, with the same layout as the thin trait objects generated by it. Their vtable entries use the `"C"` ABI and take the pointer to the object followed by the arguments, but unlike the ones above, they abort the process if the iterator or closure panics. The `next` entry of the iterator writes the item through a pointer and returns whether there was one:
+//! ```rust
+//! use std::mem::MaybeUninit;
+//! use thin_trait_object_runtime::ThinIterator;
+//!
+//! let mut numbers = ThinIterator::new(1..=3_u32);
+//! assert_eq!(numbers.size_hint(), (3, Some(3)));
+//! assert_eq!(numbers.next(), Some(1));
+//! // This is how C code would retrieve an item.
+//! let mut item = MaybeUninit::uninit();
+//! assert!(unsafe { (numbers.vtable().next)(numbers.as_raw() as _, item.as_mut_ptr()) });
+//! assert_eq!(unsafe { item.assume_init() }, 2);
+//! assert_eq!(numbers.collect::<Vec<_>>(), [3]);
+//! ```
+//!
+//! The thin closures take their arguments as a tuple:
+//! ```rust
+//! use thin_trait_object_runtime::{ThinFn, ThinFnMut, ThinFnOnce};
+//!
+//! let add = ThinFn::<(u32, u32), u32>::new(|a, b| a + b);
+//! assert_eq!(add.call(2, 40), 42);
+//! // This is how C code would call it.
+//! assert_eq!(unsafe { (add.vtable().call)(add.as_raw() as _, 2, 40) }, 42);
+//!
+//! let mut total = 0;
+//! let mut counter = ThinFnMut::<(), u32>::new(move || {
+//!     total += 1;
+//!     total
+//! });
+//! counter.call_mut();
+//! assert_eq!(counter.call_mut(), 2);
+//!
+//! let text = String::from("Hello World!");
+//! let take = ThinFnOnce::<(), String>::new(move || text);
+//! assert_eq!(take.call_once(), "Hello World!");
+//! ```
+
+#![deny(rust_2018_idioms)]
+#![warn(missing_docs, clippy::cargo)]
+
+use std::{
+    any::Any,
+    error::Error,
+    io::{Read, Write},
+};
+use thin_trait_object::thin_trait_object;
+
+mod closure;
+mod iterator;
+mod raw;
+pub use closure::*;
+pub use iterator::*;
+
+/// A thin trait object, which is one pointer wide and owns a value along with the vtable of the trait the value implements.
+///
+/// Implemented by the thin trait objects generated with the `runtime` option of `#[thin_trait_object]`, which forward all of the methods to their inherent counterparts.
+///
+/// # Safety
+/// The pointer returned by [`as_raw`] and [`into_raw`] must point to an object which the vtable returned by [`vtable`] can be used with, and [`from_raw`] must accept such a pointer, taking ownership of the object.
+///
+/// [`as_raw`]: #tymethod.as_raw " "
+/// [`into_raw`]: #method.into_raw " "
+/// [`vtable`]: #tymethod.vtable " "
+/// [`from_raw`]: #tymethod.from_raw " "
+pub unsafe trait ThinTraitObject: Sized {
+    /// The vtable struct of the trait.
+    type Vtable;
+    /// Creates a thin trait object directly from a raw pointer to it.
+    ///
+    /// # Safety
+    /// The pointer must uphold the same invariants as the ones for the `from_raw` inherent method of the thin trait object.
+    unsafe fn from_raw(ptr: *mut ()) -> Self;
+    /// Extracts the contained pointer to the trait object without releasing ownership of it.
+    fn as_raw(&self) -> *mut ();
+    /// Releases ownership of the trait object, returning the contained pointer. It is the caller's responsibility to drop the trait object at a later time using [`from_raw`].
+    ///
+    /// [`from_raw`]: #tymethod.from_raw " "
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        let pointer = self.as_raw();
+        std::mem::forget(self);
+        pointer
+    }
+    /// Retrieves the vtable of the contained trait object.
+    fn vtable(&self) -> &Self::Vtable;
+}
+
+/// A reader which can be stored in a [`ThinRead`], implemented for all types which implement `io::Read` and `Send`.
+#[thin_trait_object(
+    vtable(
+        /// The vtable of [`ThinRead`].
+        #[allow(missing_docs)] // The entries are those of the trait methods.
+        pub DynReadVtable
+    ),
+    trait_object(
+        /// A thin `Read` trait object.
+        pub ThinRead
+    ),
+    abi = "C",
+    on_panic = "catch_and_store",
+    blanket_impl = true,
+    runtime = crate,
+)]
+pub trait DynRead: Read + Send + 'static {}
+
+/// A writer which can be stored in a [`ThinWrite`], implemented for all types which implement `io::Write` and `Send`.
+#[thin_trait_object(
+    vtable(
+        /// The vtable of [`ThinWrite`].
+        #[allow(missing_docs)] // The entries are those of the trait methods.
+        pub DynWriteVtable
+    ),
+    trait_object(
+        /// A thin `Write` trait object.
+        pub ThinWrite
+    ),
+    abi = "C",
+    on_panic = "catch_and_store",
+    blanket_impl = true,
+    runtime = crate,
+)]
+pub trait DynWrite: Write + Send + 'static {}
+
+/// An error which can be stored in a [`ThinError`], implemented for all types which implement `Error`, `Send` and `Sync`.
+#[thin_trait_object(
+    vtable(
+        /// The vtable of [`ThinError`].
+        #[allow(missing_docs)] // The entries are those of the trait methods.
+        pub DynErrorVtable
+    ),
+    trait_object(
+        /// A thin `Error` trait object, which can be converted into `Box<dyn Error + Send + Sync>` with the `?` operator.
+        pub ThinError
+    ),
+    abi = "C",
+    on_panic = "catch_and_store",
+    blanket_impl = true,
+    runtime = crate,
+)]
+pub trait DynError: Error + Send + Sync + 'static {}
+
+/// A value which can be stored in a [`ThinAny`], implemented for all types which implement `Any` and `Send`.
+#[thin_trait_object(
+    vtable(
+        /// The vtable of [`ThinAny`].
+        #[allow(missing_docs)] // The entries are those of the trait methods.
+        pub DynAnyVtable
+    ),
+    trait_object(
+        /// A thin `Any` trait object, which can be downcast to the type of the value.
+        pub ThinAny
+    ),
+    abi = "C",
+    on_panic = "catch_and_store",
+    blanket_impl = true,
+    runtime = crate,
+)]
+pub trait DynAny: Any + Send + 'static {}
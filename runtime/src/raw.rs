@@ -0,0 +1,57 @@
+//! The allocation which the generic thin trait objects of this crate point to, laid out the same way as that of the thin trait objects generated by `#[thin_trait_object]`.
+
+use std::{
+    ffi::c_void,
+    panic::{self, AssertUnwindSafe},
+    process,
+};
+
+/// The vtable pointer, followed by the value.
+#[repr(C)]
+pub(crate) struct Repr<V, T> {
+    vtable: *const V,
+    value: T,
+}
+
+/// Moves the value into a new allocation after the vtable pointer, returning the pointer to the allocation.
+///
+/// # Safety
+/// The vtable must outlive the allocation, which is the case for a reference to a constant promoted to static memory, even if the lifetime of the reference is shorter because the vtable type is not `'static`.
+pub(crate) unsafe fn create<V, T>(vtable: *const V, value: T) -> *mut () {
+    Box::into_raw(Box::new(Repr { vtable, value })) as *mut ()
+}
+
+/// Retrieves the vtable which the allocation points to.
+///
+/// # Safety
+/// The pointer must point to a `Repr` with a valid vtable pointer.
+pub(crate) unsafe fn vtable<'a, V>(ptr: *mut ()) -> &'a V {
+    &**(ptr as *const *const V)
+}
+
+/// Retrieves a pointer to the value inside the allocation.
+///
+/// # Safety
+/// The pointer must point to a `Repr` holding a value of type `T`.
+pub(crate) unsafe fn value<V, T>(ptr: *mut c_void) -> *mut T {
+    &mut (*(ptr as *mut Repr<V, T>)).value
+}
+
+/// Moves the value out of the allocation and frees it.
+///
+/// # Safety
+/// The pointer must point to a `Repr` holding a value of type `T`, which must not be used afterwards.
+pub(crate) unsafe fn take<V, T>(ptr: *mut c_void) -> T {
+    let repr = *Box::from_raw(ptr as *mut Repr<V, T>);
+    repr.value
+}
+
+/// The `drop` vtable entry, which drops the value and frees the allocation.
+pub(crate) unsafe extern "C" fn drop<V, T>(ptr: *mut c_void) {
+    abort_on_panic(|| std::mem::drop(take::<V, T>(ptr)));
+}
+
+/// Runs the body of a vtable entry, aborting the process if it panics, since a panic cannot unwind through the `"C"` ABI.
+pub(crate) fn abort_on_panic<R>(f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| process::abort())
+}
@@ -1,6 +1,18 @@
 //! The main body of the attribute macro. Uses entirely `proc_macro2` stuff to make unit testing possible — compile error conversions and `proc_macro` conversions are delegated to the crate root wrapper.
 
-use super::{marker_traits::*, options::*, repr::*, trait_object::*, vtable::*};
+use super::{
+    closure::*,
+    dyn_conversions::*,
+    forward::*,
+    lowering::*,
+    marker_traits::*,
+    marshal::*,
+    options::*,
+    repr::*,
+    trait_object::*,
+    unwind::*,
+    vtable::*,
+};
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{format_ident, quote};
 use std::convert::TryFrom;
@@ -14,11 +26,18 @@ use syn::{
     PathArguments,
     PathSegment,
     TraitBound,
+    Type,
     Visibility,
 };
 
 pub fn attribute_main(attr: TokenStream, item: TokenStream) -> Result<TokenStream, syn::Error> {
     let options = Punctuated::parse_terminated.parse2(attr)?;
+    reject_options(&options, |option| match option {
+        AttrOption::TraitDef { .. } | AttrOption::DropSlot { .. } => {
+            Some("this option can only be used with `#[thin_trait_object_from_vtable]`")
+        }
+        _ => None,
+    })?;
     let config = Config::from(options);
     let trait_def = syn::parse2::<ItemTrait>(item)?;
     if !trait_def.generics.params.is_empty() {
@@ -27,17 +46,78 @@ pub fn attribute_main(attr: TokenStream, item: TokenStream) -> Result<TokenStrea
             "generic traits are not yet supported by #[thin_trait_object]",
         ));
     }
+    let closure_item = closure_item(&trait_def)?;
+    let closure_impl = if closure_item.is_some() {
+        generate_closure_impl(&trait_def)
+    } else {
+        quote! {}
+    };
+    let blanket_impl = if config.blanket_impl {
+        if let Some(item) = trait_def.items.first() {
+            return Err(syn::Error::new_spanned(
+                item,
+                "`blanket_impl` can only be used with traits which have no items of their own",
+            ));
+        }
+        generate_blanket_impl(&trait_def)
+    } else {
+        quote! {}
+    };
+    let forwarded_traits = forwarded_traits(&trait_def.supertraits)?;
     let vtable_name = config
         .vtable_name
         .unwrap_or_else(|| format_ident!("{}Vtable", &trait_def.ident));
     let trait_object_name = config
         .trait_object_name
         .unwrap_or_else(|| format_ident!("Boxed{}", &trait_def.ident));
-    let vtable_items = trait_def
+    let mut vtable_items = trait_def
         .items
         .into_iter()
         .map(VtableItem::try_from)
         .collect::<Result<Vec<_>, _>>()?;
+    // The methods for the `Box<dyn Trait>` implementation, before the vtable-specific adjustments.
+    let dyn_items = if config.dyn_conversions {
+        check_dyn_conversions(
+            &trait_def.ident,
+            &vtable_items,
+            &forwarded_traits,
+            closure_item.is_some(),
+        )?;
+        Some(vtable_items.clone())
+    } else {
+        None
+    };
+    vtable_items.extend(closure_item);
+    if let Some(abi) = &config.abi {
+        for item in vtable_items.iter_mut().filter(|item| item.abi.is_none()) {
+            item.slot_abi = Some(abi.clone());
+        }
+    }
+    // Those already have the ABI, which determines their signature.
+    vtable_items.extend(forwarded_items(&forwarded_traits, config.abi.as_ref()));
+    // Methods which can unwind get vtable entries which cannot, with the thunk catching the panic.
+    if config.panic_policy.is_some() {
+        for item in &mut vtable_items {
+            if let Some(slot_abi) = item.abi.as_ref().and_then(without_unwind) {
+                item.slot_abi = Some(slot_abi);
+            }
+        }
+    }
+    if config.lower_results {
+        lower_results(&mut vtable_items);
+    }
+    let buffer_name = buffer_name_from_trait_name(&trait_def.ident);
+    if config.marshal || !config.thin_traits.is_empty() {
+        marshal_items(
+            &mut vtable_items,
+            if config.marshal {
+                Some(&buffer_name)
+            } else {
+                None
+            },
+            &config.thin_traits,
+        );
+    }
     let (markers, lifetime_bounds) = supertraits_to_markers_and_lifetimes(
         trait_def.supertraits,
         config.marker_traits.map_or(
@@ -62,7 +142,18 @@ pub fn attribute_main(attr: TokenStream, item: TokenStream) -> Result<TokenStrea
         vtable_name,
         trait_object_name,
         repr_name: repr_name_from_trait_name(trait_def.ident.clone()),
+        prefix_name: prefix_name_from_trait_name(trait_def.ident.clone()),
         vtable_items,
+        drop_slot: DropSlot {
+            abi: config.drop_abi.or(config.abi),
+            ..DropSlot::default()
+        },
+        header: config.header,
+        panic_policy: config.panic_policy,
+        forwarded_traits,
+        runtime: config.runtime,
+        stack_objects: config.stack_objects,
+        inline_storage: config.inline_storage,
     };
     let trait_visibility = &trait_def.vis;
     let vtable = generate_vtable(
@@ -71,14 +162,13 @@ pub fn attribute_main(attr: TokenStream, item: TokenStream) -> Result<TokenStrea
             .vtable_visibility
             .unwrap_or_else(|| trait_visibility.clone()),
         config.vtable_attributes,
-        config.drop_abi.as_ref(),
         config.store_layout,
     );
     let repr = generate_repr(
         &mut stash,
         config.inline_vtable,
         path_to_box(),
-        config.drop_abi.as_ref(),
+        config.allocator.as_ref(),
         config.store_layout,
     );
     let trait_object = generate_trait_object(
@@ -88,28 +178,82 @@ pub fn attribute_main(attr: TokenStream, item: TokenStream) -> Result<TokenStrea
             .unwrap_or_else(|| trait_visibility.clone()),
         config.inline_vtable,
         has_static_bound,
+        config.blanket_impl,
         &config.trait_object_attributes,
         &markers,
     )?;
+    let ffi_checks = if config.strict_ffi {
+        generate_ffi_checks(&stash)
+    } else {
+        quote! {}
+    };
+    let dyn_conversions = if let Some(dyn_items) = dyn_items {
+        generate_dyn_conversions(
+            &stash,
+            trait_def.unsafety,
+            dyn_items,
+            &path_to_box(),
+            config.inline_vtable,
+            has_static_bound,
+            config.blanket_impl,
+        )
+    } else {
+        quote! {}
+    };
+    let buffer = if config.marshal {
+        generate_buffer(&buffer_name, trait_visibility)
+    } else {
+        quote! {}
+    };
     // We don't need to add the original input to the output here because the
     // public wrapper does that, see its definition for more on that.
     let output = quote! {
-        #vtable #repr #trait_object
+        #vtable #repr #trait_object #ffi_checks #buffer #closure_impl #blanket_impl
+        #dyn_conversions
     };
     Ok(output)
 }
 
-struct Config {
-    vtable_attributes: Vec<Attribute>,
-    vtable_visibility: Option<Visibility>,
-    vtable_name: Option<Ident>,
-    inline_vtable: bool,
-    trait_object_attributes: Vec<Attribute>,
-    trait_object_visibility: Option<Visibility>,
-    trait_object_name: Option<Ident>,
-    drop_abi: Option<Abi>,
-    marker_traits: Option<Vec<MarkerTrait>>,
-    store_layout: bool,
+/// Implements the trait for all types which implement its supertraits, including the thin trait object if it implements them too.
+fn generate_blanket_impl(trait_def: &ItemTrait) -> TokenStream {
+    let trait_name = &trait_def.ident;
+    let unsafety = &trait_def.unsafety;
+    let supertraits = &trait_def.supertraits;
+    quote! {
+        #unsafety impl<
+            __ThinTraitObjectMacro_Implementor: #supertraits
+        > #trait_name for __ThinTraitObjectMacro_Implementor {}
+    }
+}
+
+pub struct Config {
+    pub vtable_attributes: Vec<Attribute>,
+    pub vtable_visibility: Option<Visibility>,
+    pub vtable_name: Option<Ident>,
+    pub inline_vtable: bool,
+    pub trait_object_attributes: Vec<Attribute>,
+    pub trait_object_visibility: Option<Visibility>,
+    pub trait_object_name: Option<Ident>,
+    pub abi: Option<Abi>,
+    pub drop_abi: Option<Abi>,
+    pub marker_traits: Option<Vec<MarkerTrait>>,
+    pub store_layout: bool,
+    pub header: Option<Type>,
+    pub trait_def_attributes: Vec<Attribute>,
+    pub trait_def_visibility: Option<Visibility>,
+    pub trait_def_name: Option<Ident>,
+    pub drop_slot: Option<Ident>,
+    pub panic_policy: Option<PanicPolicy>,
+    pub lower_results: bool,
+    pub strict_ffi: bool,
+    pub marshal: bool,
+    pub thin_traits: Vec<ThinTrait>,
+    pub allocator: Option<Path>,
+    pub blanket_impl: bool,
+    pub runtime: Option<Path>,
+    pub dyn_conversions: bool,
+    pub stack_objects: bool,
+    pub inline_storage: bool,
 }
 impl From<AttrOptions> for Config {
     fn from(options: AttrOptions) -> Self {
@@ -129,6 +273,12 @@ impl From<AttrOptions> for Config {
                     config.trait_object_visibility = Some(additions.visibility);
                     config.trait_object_name = Some(additions.name);
                 }
+                AttrOption::Abi { abi, .. } => {
+                    config.abi = Some(Abi {
+                        extern_token: Default::default(),
+                        name: Some(abi),
+                    })
+                }
                 AttrOption::DropAbi { abi, .. } => {
                     config.drop_abi = Some(Abi {
                         extern_token: Default::default(),
@@ -141,6 +291,50 @@ impl From<AttrOptions> for Config {
                 AttrOption::StoreLayout { val, .. } => {
                     config.store_layout = val.value;
                 }
+                AttrOption::Header { ty, .. } => {
+                    config.header = Some(ty);
+                }
+                AttrOption::TraitDef { additions, .. } => {
+                    config.trait_def_attributes = additions.attributes;
+                    config.trait_def_visibility = Some(additions.visibility);
+                    config.trait_def_name = Some(additions.name);
+                }
+                AttrOption::DropSlot { slot, .. } => {
+                    config.drop_slot = Some(slot);
+                }
+                AttrOption::OnPanic { policy, .. } => {
+                    config.panic_policy = Some(policy);
+                }
+                AttrOption::LowerResults { val, .. } => {
+                    config.lower_results = val.value;
+                }
+                AttrOption::StrictFfi { val, .. } => {
+                    config.strict_ffi = val.value;
+                }
+                AttrOption::Marshal { val, .. } => {
+                    config.marshal = val.value;
+                }
+                AttrOption::ThinTraits { thin_traits, .. } => {
+                    config.thin_traits = thin_traits.into_iter().collect();
+                }
+                AttrOption::Allocator { allocator, .. } => {
+                    config.allocator = Some(allocator);
+                }
+                AttrOption::BlanketImpl { val, .. } => {
+                    config.blanket_impl = val.value;
+                }
+                AttrOption::Runtime { path, .. } => {
+                    config.runtime = Some(path);
+                }
+                AttrOption::DynConversions { val, .. } => {
+                    config.dyn_conversions = val.value;
+                }
+                AttrOption::StackObjects { val, .. } => {
+                    config.stack_objects = val.value;
+                }
+                AttrOption::InlineStorage { val, .. } => {
+                    config.inline_storage = val.value;
+                }
             }
         }
         config
@@ -149,6 +343,7 @@ impl From<AttrOptions> for Config {
 // Not using the derive because at some point I'm gonna introduce a config entry which should have
 // a different default and then forget to refator into a manual implementation, which will take
 // some time to figure out when it becomes a bug.
+#[allow(clippy::derivable_impls)]
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -159,11 +354,41 @@ impl Default for Config {
             trait_object_attributes: Vec::new(),
             trait_object_visibility: None,
             trait_object_name: None,
+            abi: None,
             drop_abi: None,
             marker_traits: None,
             store_layout: false,
+            header: None,
+            trait_def_attributes: Vec::new(),
+            trait_def_visibility: None,
+            trait_def_name: None,
+            drop_slot: None,
+            panic_policy: None,
+            lower_results: false,
+            strict_ffi: false,
+            marshal: false,
+            thin_traits: Vec::new(),
+            allocator: None,
+            blanket_impl: false,
+            runtime: None,
+            dyn_conversions: false,
+            stack_objects: false,
+            inline_storage: false,
+        }
+    }
+}
+
+/// Produces an error for the first option for which `reason` returns an explanation of why it cannot be used.
+pub fn reject_options(
+    options: &AttrOptions,
+    mut reason: impl FnMut(&AttrOption) -> Option<&'static str>,
+) -> syn::Result<()> {
+    for option in options {
+        if let Some(reason) = reason(option) {
+            return Err(syn::Error::new(option.name_span(), reason));
         }
     }
+    Ok(())
 }
 
 /// A "shared stash" for the things that the various stages of the macro will store and use, used to clean up code and improve argument passing performance.
@@ -171,11 +396,19 @@ pub struct StageStash {
     pub trait_name: Ident,
     pub vtable_name: Ident,
     pub repr_name: Ident,
+    pub prefix_name: Ident,
     pub trait_object_name: Ident,
     pub vtable_items: Vec<VtableItem>,
+    pub drop_slot: DropSlot,
+    pub header: Option<Type>,
+    pub panic_policy: Option<PanicPolicy>,
+    pub forwarded_traits: Vec<ForwardedTrait>,
+    pub runtime: Option<Path>,
+    pub stack_objects: bool,
+    pub inline_storage: bool,
 }
 
-fn path_to_box() -> Path {
+pub fn path_to_box() -> Path {
     let mut segments = Punctuated::new();
     let mut push_segment = |name| {
         segments.push(PathSegment {
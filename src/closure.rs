@@ -0,0 +1,128 @@
+//! Handling of `Fn`, `FnMut` and `FnOnce` supertraits, which turn the trait into a thin closure type.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::{BareFnArg, ItemTrait, PathArguments, Receiver, TraitBound, TypeParamBound};
+
+use crate::vtable::{VtableFnArg, VtableItem};
+
+/// Which of the closure traits is a supertrait, determining the receiver of the call slot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClosureKind {
+    Fn,
+    FnMut,
+    FnOnce,
+}
+impl ClosureKind {
+    /// Recognizes `Fn(...) -> ...` and friends, with or without a path leading to them.
+    fn from_bound(bound: &TraitBound) -> Option<Self> {
+        let segment = bound.path.segments.last()?;
+        if !matches!(segment.arguments, PathArguments::Parenthesized(..)) {
+            return None;
+        }
+        if segment.ident == "Fn" {
+            Some(Self::Fn)
+        } else if segment.ident == "FnMut" {
+            Some(Self::FnMut)
+        } else if segment.ident == "FnOnce" {
+            Some(Self::FnOnce)
+        } else {
+            None
+        }
+    }
+    /// The name of the vtable entry and of the method of the thin trait object which calls the closure.
+    pub fn method_name(self) -> Ident {
+        let name = match self {
+            Self::Fn => "call",
+            Self::FnMut => "call_mut",
+            Self::FnOnce => "call_once",
+        };
+        Ident::new(name, Span::call_site())
+    }
+    fn receiver(self) -> Receiver {
+        Receiver {
+            attrs: Vec::new(),
+            reference: match self {
+                Self::Fn | Self::FnMut => Some((Default::default(), None)),
+                Self::FnOnce => None,
+            },
+            mutability: match self {
+                Self::FnMut => Some(Default::default()),
+                Self::Fn | Self::FnOnce => None,
+            },
+            self_token: Default::default(),
+        }
+    }
+}
+
+/// Creates the vtable entry for the closure supertrait of the trait, if it has one.
+pub fn closure_item(trait_def: &ItemTrait) -> syn::Result<Option<VtableItem>> {
+    let mut closure_bounds = trait_def
+        .supertraits
+        .iter()
+        .filter_map(|bound| match bound {
+            TypeParamBound::Trait(bound) => {
+                ClosureKind::from_bound(bound).map(|kind| (kind, bound))
+            }
+            TypeParamBound::Lifetime(..) => None,
+        });
+    let (kind, bound) = match closure_bounds.next() {
+        Some(closure_bound) => closure_bound,
+        None => return Ok(None),
+    };
+    if let Some((_, extra_bound)) = closure_bounds.next() {
+        return Err(syn::Error::new_spanned(
+            extra_bound,
+            "a thin closure trait can only have one `Fn`, `FnMut` or `FnOnce` supertrait",
+        ));
+    }
+    if let Some(item) = trait_def.items.first() {
+        return Err(syn::Error::new_spanned(
+            item,
+            "traits with an `Fn`, `FnMut` or `FnOnce` supertrait cannot have items of their own",
+        ));
+    }
+    let arguments = match &bound.path.segments.last().unwrap().arguments {
+        PathArguments::Parenthesized(arguments) => arguments,
+        _ => unreachable!("checked by ClosureKind::from_bound"),
+    };
+    let inputs = Some(VtableFnArg::Receiver(kind.receiver()))
+        .into_iter()
+        .chain(arguments.inputs.iter().map(|ty| {
+            VtableFnArg::Normal(BareFnArg {
+                attrs: Vec::new(),
+                name: None,
+                ty: ty.clone(),
+            })
+        }))
+        .collect();
+    Ok(Some(VtableItem {
+        lifetimes: bound.lifetimes.clone().unwrap_or_default(),
+        unsafety: None,
+        abi: None,
+        name: kind.method_name(),
+        inputs,
+        variadic: None,
+        output: arguments.output.clone(),
+        raw_receiver: None,
+        nullable: false,
+        slot_abi: None,
+        lowered_result: None,
+        marshalled_args: Vec::new(),
+        marshalled_output: None,
+        closure: Some(kind),
+        forwarded: None,
+    }))
+}
+
+/// Implements the trait for all closures which implement its supertraits.
+pub fn generate_closure_impl(trait_def: &ItemTrait) -> TokenStream {
+    let trait_name = &trait_def.ident;
+    let unsafety = &trait_def.unsafety;
+    let supertraits = &trait_def.supertraits;
+    quote! {
+        #unsafety impl<
+            __ThinTraitObjectMacro_Closure: #supertraits
+        > #trait_name for __ThinTraitObjectMacro_Closure {}
+    }
+}
@@ -0,0 +1,194 @@
+//! Conversions between the thin trait object and `Box<dyn Trait>`, for code which uses both.
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+use syn::{punctuated::Punctuated, token, FnArg, Path, Token};
+
+use crate::{attr::StageStash, forward::ForwardedTrait, vtable::VtableItem};
+
+/// Checks whether `Box<dyn Trait>` can implement the trait.
+pub fn check_dyn_conversions(
+    trait_name: &Ident,
+    vtable_items: &[VtableItem],
+    forwarded_traits: &[ForwardedTrait],
+    is_closure: bool,
+) -> syn::Result<()> {
+    if is_closure {
+        return Err(syn::Error::new_spanned(
+            trait_name,
+            "`dyn_conversions` cannot be used with thin closures, since the thin trait object does \
+not implement the trait",
+        ));
+    }
+    // `Box` is `Unpin`, so pinning the box does not pin the value inside it.
+    if let Some(item) = vtable_items
+        .iter()
+        .find(|item| item.pinned_receiver().is_some())
+    {
+        return Err(syn::Error::new_spanned(
+            &item.name,
+            "`dyn_conversions` cannot be used with methods which take pinned receivers, since `Box<dyn Trait>` is `Unpin` and would let the value be moved out of it",
+        ));
+    }
+    for forwarded_trait in forwarded_traits {
+        let reason = match forwarded_trait {
+            ForwardedTrait::PartialEq
+            | ForwardedTrait::Eq
+            | ForwardedTrait::PartialOrd
+            | ForwardedTrait::Ord
+            | ForwardedTrait::Hash => {
+                "`dyn_conversions` cannot be used with comparison and hashing supertraits, since \
+they make the trait unusable as a trait object"
+            }
+            ForwardedTrait::Error => {
+                "`dyn_conversions` cannot be used with `Error` as a supertrait, since it's not \
+implemented by `Box<dyn Error>`"
+            }
+            ForwardedTrait::Any => {
+                "`dyn_conversions` cannot be used with `Any` as a supertrait, since downcasts would \
+see the box instead of the value inside it"
+            }
+            _ => continue,
+        };
+        return Err(syn::Error::new_spanned(trait_name, reason));
+    }
+    Ok(())
+}
+
+/// Implements the trait for `Box<dyn Trait>`, unless there is a blanket implementation which covers it already, and generates the conversions between it and the thin trait object.
+pub fn generate_dyn_conversions(
+    stash: &StageStash,
+    unsafety: Option<Token![unsafe]>,
+    items: Vec<VtableItem>,
+    path_to_box: &Path,
+    inline_vtable: bool,
+    has_static_bound: bool,
+    blanket_impl: bool,
+) -> TokenStream {
+    let StageStash {
+        trait_name,
+        trait_object_name,
+        vtable_name,
+        repr_name,
+        prefix_name,
+        header,
+        ..
+    } = stash;
+    let (generics, dyn_lifetime) = if has_static_bound {
+        (quote! {}, quote! {})
+    } else {
+        (quote! { <'inner> }, quote! { + 'inner })
+    };
+    let dyn_impl = if blanket_impl {
+        quote! {}
+    } else {
+        let methods = items.into_iter().map(forward_to_dyn);
+        quote! {
+            #unsafety impl #generics #trait_name for #path_to_box<dyn #trait_name #dyn_lifetime> {
+                #(#methods)*
+            }
+        }
+    };
+    let (header_bound, new) = if let Some(header) = header {
+        (
+            quote! {
+                where for<'__thintraitobjectmacro_header> #header: ::core::default::Default
+            },
+            quote! { Self::new_with_header(::core::default::Default::default(), boxed) },
+        )
+    } else {
+        (quote! {}, quote! { Self::new(boxed) })
+    };
+    if inline_vtable {
+        // An inline vtable has no address to recognize the box by, so it's boxed once more.
+        return quote! {
+            #dyn_impl
+            impl #generics ::core::convert::From<#path_to_box<dyn #trait_name #dyn_lifetime>>
+                for #trait_object_name #generics
+            #header_bound
+            {
+                /// Wraps the boxed trait object into a thin one, which stores the box as its value.
+                #[inline]
+                fn from(boxed: #path_to_box<dyn #trait_name #dyn_lifetime>) -> Self {
+                    #new
+                }
+            }
+            impl #generics #trait_object_name #generics {
+                /// Converts the thin trait object into a boxed `dyn` trait object, which stores it as its value.
+                #[inline]
+                pub fn into_dyn(self) -> #path_to_box<dyn #trait_name #dyn_lifetime> {
+                    #path_to_box::new(self)
+                }
+            }
+        };
+    }
+    let vtable_field = if header.is_some() {
+        quote! {
+            (*(object.as_raw() as *mut #prefix_name)).__thintraitobjectmacro_repr_vtable
+        }
+    } else {
+        quote! { *(object.as_raw() as *mut &'static #vtable_name) }
+    };
+    quote! {
+        #dyn_impl
+        impl #generics ::core::convert::From<#path_to_box<dyn #trait_name #dyn_lifetime>>
+            for #trait_object_name #generics
+        #header_bound
+        {
+            /// Wraps the boxed trait object into a thin one, which stores the box as its value.
+            #[inline]
+            fn from(boxed: #path_to_box<dyn #trait_name #dyn_lifetime>) -> Self {
+                let object = #new;
+                // The vtable is the same, but its address marks the value as a box which
+                // `into_dyn` can give back.
+                unsafe {
+                    #vtable_field = Self::__thintraitobjectmacro_dyn_vtable();
+                }
+                object
+            }
+        }
+        impl #generics #trait_object_name #generics {
+            /// Converts the thin trait object into a boxed `dyn` trait object. If the thin trait object was created from a box, that box is given back, otherwise the thin trait object is moved into a new box and stored as its value.
+            #[inline]
+            pub fn into_dyn(self) -> #path_to_box<dyn #trait_name #dyn_lifetime> {
+                if ::core::ptr::eq(self.vtable(), Self::__thintraitobjectmacro_dyn_vtable()) {
+                    unsafe {
+                        #repr_name::<#path_to_box<dyn #trait_name #dyn_lifetime>>
+                            ::__thintraitobjectmacro_repr_take(self.into_raw() as *mut _)
+                    }
+                } else {
+                    #path_to_box::new(self)
+                }
+            }
+            // Constants may be duplicated, so a static is used to have a single address. Lifetimes
+            // don't affect the code, so the one for `'static` boxes works for all of them.
+            #[inline]
+            fn __thintraitobjectmacro_dyn_vtable() -> &'static #vtable_name {
+                static VTABLE: #vtable_name =
+                    #repr_name::<#path_to_box<dyn #trait_name>>::__THINTRAITOBJECTMACRO_VTABLE;
+                &VTABLE
+            }
+        }
+    }
+}
+
+/// Implements a method for `Box<dyn Trait>` by calling it on the contents of the box.
+fn forward_to_dyn(item: VtableItem) -> TokenStream {
+    let signature = item.into_signature(|x| format_ident!("__arg{}", x));
+    let name = &signature.ident;
+    let mut inputs = signature.inputs.iter();
+    // Skip the receiver.
+    inputs.next();
+    let args = inputs
+        .map(|input| match input {
+            FnArg::Typed(arg) => arg.pat.to_token_stream(),
+            FnArg::Receiver(..) => unreachable!("receiver after the first argument"),
+        })
+        .collect::<Punctuated<_, token::Comma>>();
+    quote! {
+        #[inline]
+        #signature {
+            (**self).#name(#args)
+        }
+    }
+}
@@ -0,0 +1,1166 @@
+//! Forwarding of standard library supertraits, such as `Debug`, through the vtable, so that the thin trait object could implement them as well.
+
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+use syn::{
+    parse_quote,
+    punctuated::Punctuated,
+    token,
+    Abi,
+    BareFnArg,
+    BoundLifetimes,
+    GenericArgument,
+    Path,
+    PathArguments,
+    Receiver,
+    ReturnType,
+    TraitBound,
+    Type,
+    TypeParamBound,
+};
+
+use crate::{
+    unwind::{resume_stored_panic, PanicPolicy},
+    vtable::{is_foreign_abi, VtableFnArg, VtableItem},
+};
+
+/// A supertrait which the thin trait object implements by calling into the vtable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ForwardedTrait {
+    Debug,
+    Display,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Error,
+    Read,
+    Write,
+    Seek,
+    BufRead,
+    /// Cannot be implemented manually, so the thin trait object gets inherent methods for downcasting instead.
+    Any,
+    /// `Iterator<Item = T>`, with the `Item` type taken from the binding.
+    Iterator {
+        item: Box<Type>,
+    },
+}
+impl ForwardedTrait {
+    /// Recognizes the trait by its name, either on its own or with the path of the module which defines it, as in `fmt::Debug` or `core::fmt::Debug`.
+    fn from_bound(bound: &TraitBound) -> syn::Result<Option<Self>> {
+        let (module, name, arguments) = match split_std_path(&bound.path) {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        if name == "Iterator" && matches!(module.as_deref(), None | Some("iter")) {
+            return match iterator_item(arguments) {
+                Some(item) => Ok(Some(Self::Iterator { item })),
+                None => Err(syn::Error::new_spanned(
+                    bound,
+                    "forwarding `Iterator` needs the `Item` type, as in `Iterator<Item = T>`",
+                )),
+            };
+        }
+        if !arguments.is_empty() {
+            return Ok(None);
+        }
+        let (expected_module, forwarded_trait) = match name.as_str() {
+            "Debug" => ("fmt", Self::Debug),
+            "Display" => ("fmt", Self::Display),
+            "PartialEq" => ("cmp", Self::PartialEq),
+            "Eq" => ("cmp", Self::Eq),
+            "PartialOrd" => ("cmp", Self::PartialOrd),
+            "Ord" => ("cmp", Self::Ord),
+            "Hash" => ("hash", Self::Hash),
+            "Error" => ("error", Self::Error),
+            "Read" => ("io", Self::Read),
+            "Write" => ("io", Self::Write),
+            "Seek" => ("io", Self::Seek),
+            "BufRead" => ("io", Self::BufRead),
+            "Any" => ("any", Self::Any),
+            _ => return Ok(None),
+        };
+        match module {
+            Some(module) if module != expected_module => Ok(None),
+            _ => Ok(Some(forwarded_trait)),
+        }
+    }
+    fn path(&self) -> TokenStream {
+        match self {
+            Self::Debug => quote! { ::core::fmt::Debug },
+            Self::Display => quote! { ::core::fmt::Display },
+            Self::PartialEq => quote! { ::core::cmp::PartialEq },
+            Self::Eq => quote! { ::core::cmp::Eq },
+            Self::PartialOrd => quote! { ::core::cmp::PartialOrd },
+            Self::Ord => quote! { ::core::cmp::Ord },
+            Self::Hash => quote! { ::core::hash::Hash },
+            Self::Error => quote! { ::std::error::Error },
+            Self::Read => quote! { ::std::io::Read },
+            Self::Write => quote! { ::std::io::Write },
+            Self::Seek => quote! { ::std::io::Seek },
+            Self::BufRead => quote! { ::std::io::BufRead },
+            Self::Any => quote! { ::core::any::Any },
+            Self::Iterator { .. } => quote! { ::core::iter::Iterator },
+        }
+    }
+    /// The forwarded traits which the thin trait object has to implement for this one to be implemented, including itself.
+    fn with_supertraits(self) -> Vec<Self> {
+        match self {
+            Self::Eq => vec![Self::PartialEq, Self::Eq],
+            Self::PartialOrd => vec![Self::PartialEq, Self::PartialOrd],
+            Self::Ord => vec![Self::PartialEq, Self::Eq, Self::PartialOrd, Self::Ord],
+            Self::Error => vec![Self::Debug, Self::Display, Self::Error],
+            Self::BufRead => vec![Self::Read, Self::BufRead],
+            other => vec![other],
+        }
+    }
+    fn methods(&self) -> &'static [ForwardedMethod] {
+        match self {
+            Self::Debug => &[ForwardedMethod::FmtDebug],
+            Self::Display => &[ForwardedMethod::FmtDisplay],
+            // All comparisons check the types of the values first.
+            Self::PartialEq => &[ForwardedMethod::TypeId, ForwardedMethod::Eq],
+            Self::Eq => &[],
+            Self::PartialOrd => &[ForwardedMethod::PartialCmp],
+            Self::Ord => &[ForwardedMethod::Cmp],
+            Self::Hash => &[ForwardedMethod::Hash],
+            Self::Error => &[ForwardedMethod::Source],
+            Self::Read => &[ForwardedMethod::Read],
+            Self::Write => &[ForwardedMethod::Write, ForwardedMethod::Flush],
+            Self::Seek => &[ForwardedMethod::Seek],
+            Self::BufRead => &[ForwardedMethod::FillBuf, ForwardedMethod::Consume],
+            Self::Any => &[ForwardedMethod::TypeId],
+            Self::Iterator { .. } => &[ForwardedMethod::Next, ForwardedMethod::SizeHint],
+        }
+    }
+    /// Additional items of the implementation of the trait on the thin trait object, other than the methods.
+    fn associated_items(&self) -> TokenStream {
+        match self {
+            Self::Iterator { item } => quote! { type Item = #item; },
+            _ => quote! {},
+        }
+    }
+}
+
+/// Finds the `Item` type in the generic arguments of `Iterator<Item = T>`.
+fn iterator_item(arguments: &PathArguments) -> Option<Box<Type>> {
+    let arguments = match arguments {
+        PathArguments::AngleBracketed(arguments) => &arguments.args,
+        _ => return None,
+    };
+    arguments.iter().find_map(|argument| match argument {
+        GenericArgument::Binding(binding) if binding.ident == "Item" => {
+            Some(Box::new(binding.ty.clone()))
+        }
+        _ => None,
+    })
+}
+
+/// Splits a path to a standard library trait, such as `fmt::Debug` or `::core::fmt::Debug`, into the name of the module which defines it, if present, the name of the trait itself and its generic arguments.
+fn split_std_path(path: &Path) -> Option<(Option<String>, String, &PathArguments)> {
+    let last = path.segments.last()?;
+    let modules = path
+        .segments
+        .iter()
+        .take(path.segments.len() - 1)
+        .map(|segment| match segment.arguments {
+            PathArguments::None => Some(segment.ident.to_string()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let module = match modules.as_slice() {
+        [] if path.leading_colon.is_none() => None,
+        [module] if path.leading_colon.is_none() => Some(module.clone()),
+        [krate, module] if krate == "core" || krate == "std" => Some(module.clone()),
+        _ => return None,
+    };
+    Some((module, last.ident.to_string(), &last.arguments))
+}
+
+/// A method of a forwarded supertrait, which gets its own vtable entry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ForwardedMethod {
+    FmtDebug,
+    FmtDisplay,
+    /// Returns the `TypeId` of the value, which is compared before calling the comparison entries.
+    TypeId,
+    Eq,
+    PartialCmp,
+    Cmp,
+    Hash,
+    Source,
+    Next,
+    SizeHint,
+    Read,
+    Write,
+    Flush,
+    Seek,
+    FillBuf,
+    Consume,
+}
+impl ForwardedMethod {
+    fn slot_name(self) -> Ident {
+        let name = match self {
+            Self::FmtDebug => "fmt_debug",
+            Self::FmtDisplay => "fmt_display",
+            Self::TypeId => "type_id",
+            Self::Eq => "eq",
+            Self::PartialCmp => "partial_cmp",
+            Self::Cmp => "cmp",
+            Self::Hash => "hash",
+            Self::Source => "source",
+            Self::Next => "next",
+            Self::SizeHint => "size_hint",
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Flush => "flush",
+            Self::Seek => "seek",
+            Self::FillBuf => "fill_buf",
+            Self::Consume => "consume",
+        };
+        Ident::new(name, Span::call_site())
+    }
+    /// Creates the vtable entry. Types which cannot cross the FFI boundary, such as `Formatter`, are replaced with callbacks or integers in entries with a foreign ABI.
+    fn vtable_item(self, forwarded_trait: &ForwardedTrait, slot_abi: Option<&Abi>) -> VtableItem {
+        // `TypeId` and trait objects cannot cross the FFI boundary at all, so those entries always
+        // have the Rust ABI.
+        let slot_abi = slot_abi.filter(|_| self != Self::TypeId && self != Self::Source);
+        let foreign = is_foreign_abi(slot_abi);
+        let receiver = Receiver {
+            attrs: Vec::new(),
+            reference: Some((Default::default(), None)),
+            mutability: match self {
+                Self::Next
+                | Self::Read
+                | Self::Write
+                | Self::Flush
+                | Self::Seek
+                | Self::FillBuf
+                | Self::Consume => Some(Default::default()),
+                _ => None,
+            },
+            self_token: Default::default(),
+        };
+        let iterator_item = match forwarded_trait {
+            ForwardedTrait::Iterator { item } => Some(item),
+            _ => None,
+        };
+        let other: BareFnArg = parse_quote!(other: *mut ::core::ffi::c_void);
+        let io_error_args = || -> Vec<BareFnArg> {
+            vec![
+                parse_quote! {
+                    report_error: unsafe #slot_abi fn(*mut ::core::ffi::c_void, i32, *const u8, usize)
+                },
+                parse_quote!(context: *mut ::core::ffi::c_void),
+            ]
+        };
+        let with_io_error_args = |mut inputs: Vec<BareFnArg>| {
+            inputs.extend(io_error_args());
+            (inputs, parse_quote!(-> bool))
+        };
+        let (inputs, output): (Vec<BareFnArg>, ReturnType) = match self {
+            Self::FmtDebug | Self::FmtDisplay if foreign => (
+                vec![
+                    parse_quote! {
+                        write: unsafe #slot_abi fn(*mut ::core::ffi::c_void, *const u8, usize) -> bool
+                    },
+                    parse_quote!(context: *mut ::core::ffi::c_void),
+                ],
+                parse_quote!(-> bool),
+            ),
+            Self::FmtDebug | Self::FmtDisplay => (
+                vec![parse_quote!(f: &mut ::core::fmt::Formatter<'_>)],
+                parse_quote!(-> ::core::fmt::Result),
+            ),
+            Self::TypeId => (Vec::new(), parse_quote!(-> ::core::any::TypeId)),
+            Self::Eq => (vec![other], parse_quote!(-> bool)),
+            Self::PartialCmp | Self::Cmp if foreign => (vec![other], parse_quote!(-> i8)),
+            Self::PartialCmp => (
+                vec![other],
+                parse_quote!(-> ::core::option::Option<::core::cmp::Ordering>),
+            ),
+            Self::Cmp => (vec![other], parse_quote!(-> ::core::cmp::Ordering)),
+            Self::Hash if foreign => (
+                vec![
+                    parse_quote! {
+                        write: unsafe #slot_abi fn(*mut ::core::ffi::c_void, *const u8, usize)
+                    },
+                    parse_quote!(context: *mut ::core::ffi::c_void),
+                ],
+                ReturnType::Default,
+            ),
+            Self::Hash => (
+                vec![parse_quote!(state: &mut dyn ::core::hash::Hasher)],
+                ReturnType::Default,
+            ),
+            // The item is written to `out` if there is one, which is indicated by the return value.
+            Self::Next if foreign => (
+                vec![parse_quote!(out: *mut #iterator_item)],
+                parse_quote!(-> bool),
+            ),
+            Self::Next => (
+                Vec::new(),
+                parse_quote!(-> ::core::option::Option<#iterator_item>),
+            ),
+            // Returns whether there is an upper bound.
+            Self::SizeHint if foreign => (
+                vec![
+                    parse_quote!(lower: *mut usize),
+                    parse_quote!(upper: *mut usize),
+                ],
+                parse_quote!(-> bool),
+            ),
+            Self::SizeHint => (
+                Vec::new(),
+                parse_quote!(-> (usize, ::core::option::Option<usize>)),
+            ),
+            Self::Read if foreign => with_io_error_args(vec![
+                parse_quote!(buf: *mut u8),
+                parse_quote!(len: usize),
+                parse_quote!(out: *mut usize),
+            ]),
+            Self::Read => (
+                vec![parse_quote!(buf: &mut [u8])],
+                parse_quote!(-> ::std::io::Result<usize>),
+            ),
+            Self::Write if foreign => with_io_error_args(vec![
+                parse_quote!(buf: *const u8),
+                parse_quote!(len: usize),
+                parse_quote!(out: *mut usize),
+            ]),
+            Self::Write => (
+                vec![parse_quote!(buf: &[u8])],
+                parse_quote!(-> ::std::io::Result<usize>),
+            ),
+            Self::Flush if foreign => with_io_error_args(Vec::new()),
+            Self::Flush => (Vec::new(), parse_quote!(-> ::std::io::Result<()>)),
+            Self::Seek if foreign => with_io_error_args(vec![
+                parse_quote!(whence: u8),
+                parse_quote!(offset: i64),
+                parse_quote!(out: *mut u64),
+            ]),
+            Self::Seek => (
+                vec![parse_quote!(pos: ::std::io::SeekFrom)],
+                parse_quote!(-> ::std::io::Result<u64>),
+            ),
+            Self::FillBuf if foreign => with_io_error_args(vec![
+                parse_quote!(out_ptr: *mut *const u8),
+                parse_quote!(out_len: *mut usize),
+            ]),
+            // Borrowed from the value, like the source of an error.
+            Self::FillBuf => (Vec::new(), parse_quote!(-> ::std::io::Result<*const [u8]>)),
+            Self::Consume => (vec![parse_quote!(amt: usize)], ReturnType::Default),
+            Self::Source => (
+                Vec::new(),
+                parse_quote! {
+                    -> ::core::option::Option<*const (dyn ::std::error::Error + 'static)>
+                },
+            ),
+        };
+        VtableItem {
+            lifetimes: BoundLifetimes::default(),
+            unsafety: None,
+            abi: None,
+            name: self.slot_name(),
+            inputs: Some(VtableFnArg::Receiver(receiver))
+                .into_iter()
+                .chain(inputs.into_iter().map(VtableFnArg::Normal))
+                .collect(),
+            variadic: None,
+            output,
+            raw_receiver: None,
+            nullable: false,
+            slot_abi: slot_abi.cloned(),
+            lowered_result: None,
+            marshalled_args: Vec::new(),
+            marshalled_output: None,
+            closure: None,
+            forwarded: Some(self),
+        }
+    }
+    /// The call to the method of the value inside the thunk. `value_of` turns an expression for a pointer to an object into a place expression for its value.
+    pub fn thunk_call(
+        self,
+        value_of: impl Fn(TokenStream) -> TokenStream,
+        args: &[TokenStream],
+        slot_abi: Option<&Abi>,
+    ) -> TokenStream {
+        let foreign = is_foreign_abi(slot_abi);
+        let value = value_of(quote! { __thintraitobjectmacro_arg0 });
+        let other = || value_of(args[0].clone());
+        match self {
+            Self::FmtDebug | Self::FmtDisplay if foreign => {
+                let format_string = match self {
+                    Self::FmtDebug => "{:?}",
+                    _ => "{}",
+                };
+                quote! {{
+                    struct Writer(
+                        unsafe #slot_abi fn(*mut ::core::ffi::c_void, *const u8, usize) -> bool,
+                        *mut ::core::ffi::c_void,
+                    );
+                    impl ::core::fmt::Write for Writer {
+                        fn write_str(&mut self, s: &str) -> ::core::fmt::Result {
+                            if unsafe { (self.0)(self.1, s.as_ptr(), s.len()) } {
+                                ::core::result::Result::Ok(())
+                            } else {
+                                ::core::result::Result::Err(::core::fmt::Error)
+                            }
+                        }
+                    }
+                    ::core::fmt::Write::write_fmt(
+                        &mut Writer(#(#args),*),
+                        ::core::format_args!(#format_string, #value),
+                    ).is_ok()
+                }}
+            }
+            Self::FmtDebug => quote! { ::core::fmt::Debug::fmt(&#value, #(#args),*) },
+            Self::FmtDisplay => quote! { ::core::fmt::Display::fmt(&#value, #(#args),*) },
+            Self::TypeId => quote! {
+                ::core::any::TypeId::of::<__ThinTraitObjectMacro_ReprGeneric0>()
+            },
+            Self::Eq => {
+                let other = other();
+                quote! { ::core::cmp::PartialEq::eq(&#value, &#other) }
+            }
+            Self::PartialCmp if foreign => {
+                let other = other();
+                quote! {
+                    match ::core::cmp::PartialOrd::partial_cmp(&#value, &#other) {
+                        ::core::option::Option::Some(ordering) => ordering as i8,
+                        ::core::option::Option::None => 2,
+                    }
+                }
+            }
+            Self::PartialCmp => {
+                let other = other();
+                quote! { ::core::cmp::PartialOrd::partial_cmp(&#value, &#other) }
+            }
+            Self::Cmp if foreign => {
+                let other = other();
+                quote! { ::core::cmp::Ord::cmp(&#value, &#other) as i8 }
+            }
+            Self::Cmp => {
+                let other = other();
+                quote! { ::core::cmp::Ord::cmp(&#value, &#other) }
+            }
+            Self::Hash if foreign => quote! {{
+                struct Writer(
+                    unsafe #slot_abi fn(*mut ::core::ffi::c_void, *const u8, usize),
+                    *mut ::core::ffi::c_void,
+                );
+                impl ::core::hash::Hasher for Writer {
+                    fn write(&mut self, bytes: &[u8]) {
+                        unsafe { (self.0)(self.1, bytes.as_ptr(), bytes.len()) }
+                    }
+                    // Only the thin trait object gets to finish hashing.
+                    fn finish(&self) -> u64 {
+                        ::core::unreachable!()
+                    }
+                }
+                ::core::hash::Hash::hash(&#value, &mut Writer(#(#args),*))
+            }},
+            Self::Hash => quote! {{
+                let mut state = #(#args),*;
+                ::core::hash::Hash::hash(&#value, &mut state)
+            }},
+            Self::Next if foreign => quote! {
+                match ::core::iter::Iterator::next(&mut #value) {
+                    ::core::option::Option::Some(item) => {
+                        ::core::ptr::write(#(#args),*, item);
+                        true
+                    }
+                    ::core::option::Option::None => false,
+                }
+            },
+            Self::Next => quote! { ::core::iter::Iterator::next(&mut #value) },
+            Self::SizeHint if foreign => {
+                let (lower, upper) = (&args[0], &args[1]);
+                quote! {{
+                    let hint = ::core::iter::Iterator::size_hint(&#value);
+                    *#lower = hint.0;
+                    match hint.1 {
+                        ::core::option::Option::Some(upper_bound) => {
+                            *#upper = upper_bound;
+                            true
+                        }
+                        ::core::option::Option::None => false,
+                    }
+                }}
+            }
+            Self::SizeHint => quote! { ::core::iter::Iterator::size_hint(&#value) },
+            Self::Read if foreign => {
+                let (buf, len, out) = (&args[0], &args[1], &args[2]);
+                let slice = slice_from_foreign(buf, len, true);
+                report_io_error(
+                    quote! {
+                        ::std::io::Read::read(&mut #value, #slice)
+                    },
+                    quote! { *#out = value; },
+                    &args[3..],
+                )
+            }
+            Self::Read => quote! { ::std::io::Read::read(&mut #value, #(#args),*) },
+            Self::Write if foreign => {
+                let (buf, len, out) = (&args[0], &args[1], &args[2]);
+                let slice = slice_from_foreign(buf, len, false);
+                report_io_error(
+                    quote! {
+                        ::std::io::Write::write(&mut #value, #slice)
+                    },
+                    quote! { *#out = value; },
+                    &args[3..],
+                )
+            }
+            Self::Write => quote! { ::std::io::Write::write(&mut #value, #(#args),*) },
+            Self::Flush if foreign => report_io_error(
+                quote! { ::std::io::Write::flush(&mut #value) },
+                quote! { let () = value; },
+                args,
+            ),
+            Self::Flush => quote! { ::std::io::Write::flush(&mut #value) },
+            Self::Seek if foreign => {
+                let (whence, offset, out) = (&args[0], &args[1], &args[2]);
+                report_io_error(
+                    quote! {
+                        match #whence {
+                            0 => ::std::io::Seek::seek(
+                                &mut #value,
+                                ::std::io::SeekFrom::Start(#offset as u64),
+                            ),
+                            1 => ::std::io::Seek::seek(
+                                &mut #value,
+                                ::std::io::SeekFrom::Current(#offset),
+                            ),
+                            2 => ::std::io::Seek::seek(&mut #value, ::std::io::SeekFrom::End(#offset)),
+                            _ => ::std::result::Result::Err(::std::io::Error::new(
+                                ::std::io::ErrorKind::InvalidInput,
+                                "invalid seek origin",
+                            )),
+                        }
+                    },
+                    quote! { *#out = value; },
+                    &args[3..],
+                )
+            }
+            Self::Seek => quote! { ::std::io::Seek::seek(&mut #value, #(#args),*) },
+            Self::FillBuf if foreign => {
+                let (out_ptr, out_len) = (&args[0], &args[1]);
+                report_io_error(
+                    quote! { ::std::io::BufRead::fill_buf(&mut #value) },
+                    quote! {
+                        *#out_ptr = value.as_ptr();
+                        *#out_len = value.len();
+                    },
+                    &args[2..],
+                )
+            }
+            Self::FillBuf => quote! {
+                ::std::io::BufRead::fill_buf(&mut #value).map(|buf| buf as *const [u8])
+            },
+            Self::Consume => quote! { ::std::io::BufRead::consume(&mut #value, #(#args),*) },
+            // The source is borrowed from the value, which outlives the borrow of the thin trait
+            // object through which it is returned.
+            Self::Source => quote! {
+                ::std::error::Error::source(&#value)
+                    .map(|source| source as *const (dyn ::std::error::Error + 'static))
+            },
+        }
+    }
+    /// The method of the implementation of the forwarded trait on the thin trait object, which calls `function` from the vtable, if the vtable entry corresponds to one. `cross_type_order` is set if objects with values of different types are ordered by their `TypeId` rather than being incomparable.
+    fn impl_method(
+        self,
+        item: &VtableItem,
+        function: TokenStream,
+        resume: TokenStream,
+        cross_type_order: bool,
+    ) -> TokenStream {
+        let slot_abi = item.effective_slot_abi();
+        let foreign = is_foreign_abi(slot_abi);
+        // The bytes passed to the callbacks for formatting and hashing.
+        let message = slice_from_foreign(&quote! { ptr }, &quote! { len }, false);
+        let compare_types = |different_types: TokenStream| {
+            quote! {
+                let self_type = (self.vtable().type_id)(self.as_raw() as _);
+                let other_type = (other.vtable().type_id)(other.as_raw() as _);
+                if self_type != other_type {
+                    return #different_types;
+                }
+            }
+        };
+        let call_with_other = quote! {
+            let ret = (#function)(self.as_raw() as _, other.as_raw() as _);
+            #resume
+        };
+        let (signature, body) = match self {
+            Self::FmtDebug | Self::FmtDisplay if foreign => (
+                quote! { fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result },
+                quote! {
+                    unsafe #slot_abi fn write(
+                        formatter: *mut ::core::ffi::c_void,
+                        ptr: *const u8,
+                        len: usize,
+                    ) -> bool {
+                        let formatter = &mut *(formatter as *mut ::core::fmt::Formatter<'_>);
+                        match ::core::str::from_utf8(#message) {
+                            ::core::result::Result::Ok(s) => formatter.write_str(s).is_ok(),
+                            ::core::result::Result::Err(..) => false,
+                        }
+                    }
+                    let ret = (#function)(
+                        self.as_raw() as _,
+                        write,
+                        f as *mut ::core::fmt::Formatter<'_> as *mut ::core::ffi::c_void,
+                    );
+                    #resume
+                    if ret {
+                        ::core::result::Result::Ok(())
+                    } else {
+                        ::core::result::Result::Err(::core::fmt::Error)
+                    }
+                },
+            ),
+            Self::FmtDebug | Self::FmtDisplay => (
+                quote! { fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result },
+                quote! {
+                    let ret = (#function)(self.as_raw() as _, f);
+                    #resume
+                    ret
+                },
+            ),
+            // Called by the other methods.
+            Self::TypeId => return quote! {},
+            Self::Eq => {
+                let compare_types = compare_types(quote! { false });
+                (
+                    quote! { fn eq(&self, other: &Self) -> bool },
+                    quote! {
+                        #compare_types
+                        #call_with_other
+                        ret
+                    },
+                )
+            }
+            Self::PartialCmp => {
+                let compare_types = compare_types(if cross_type_order {
+                    quote! {
+                        ::core::option::Option::Some(::core::cmp::Ord::cmp(&self_type, &other_type))
+                    }
+                } else {
+                    quote! { ::core::option::Option::None }
+                });
+                let ret = if foreign {
+                    quote! {
+                        match ret {
+                            -1 => ::core::option::Option::Some(::core::cmp::Ordering::Less),
+                            0 => ::core::option::Option::Some(::core::cmp::Ordering::Equal),
+                            1 => ::core::option::Option::Some(::core::cmp::Ordering::Greater),
+                            _ => ::core::option::Option::None,
+                        }
+                    }
+                } else {
+                    quote! { ret }
+                };
+                (
+                    quote! {
+                        fn partial_cmp(&self, other: &Self) -> ::core::option::Option<::core::cmp::Ordering>
+                    },
+                    quote! {
+                        #compare_types
+                        #call_with_other
+                        #ret
+                    },
+                )
+            }
+            Self::Cmp => {
+                let compare_types =
+                    compare_types(quote! { ::core::cmp::Ord::cmp(&self_type, &other_type) });
+                let ret = if foreign {
+                    quote! { ::core::cmp::Ord::cmp(&ret, &0) }
+                } else {
+                    quote! { ret }
+                };
+                (
+                    quote! { fn cmp(&self, other: &Self) -> ::core::cmp::Ordering },
+                    quote! {
+                        #compare_types
+                        #call_with_other
+                        #ret
+                    },
+                )
+            }
+            Self::Hash if foreign => (
+                quote! { fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) },
+                quote! {
+                    unsafe #slot_abi fn write(hasher: *mut ::core::ffi::c_void, ptr: *const u8, len: usize) {
+                        let hasher = &mut *(hasher as *mut &mut dyn ::core::hash::Hasher);
+                        hasher.write(#message);
+                    }
+                    let mut hasher: &mut dyn ::core::hash::Hasher = state;
+                    (#function)(
+                        self.as_raw() as _,
+                        write,
+                        &mut hasher as *mut &mut dyn ::core::hash::Hasher as *mut ::core::ffi::c_void,
+                    );
+                    #resume
+                },
+            ),
+            Self::Hash => (
+                quote! { fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) },
+                quote! {
+                    (#function)(self.as_raw() as _, state);
+                    #resume
+                },
+            ),
+            Self::Next if foreign => (
+                quote! { fn next(&mut self) -> ::core::option::Option<Self::Item> },
+                quote! {
+                    let mut item = ::core::mem::MaybeUninit::<Self::Item>::uninit();
+                    let ret = (#function)(self.as_raw() as _, item.as_mut_ptr());
+                    #resume
+                    if ret {
+                        ::core::option::Option::Some(item.assume_init())
+                    } else {
+                        ::core::option::Option::None
+                    }
+                },
+            ),
+            Self::Next => (
+                quote! { fn next(&mut self) -> ::core::option::Option<Self::Item> },
+                quote! {
+                    let ret = (#function)(self.as_raw() as _);
+                    #resume
+                    ret
+                },
+            ),
+            Self::SizeHint if foreign => (
+                quote! { fn size_hint(&self) -> (usize, ::core::option::Option<usize>) },
+                quote! {
+                    let (mut lower, mut upper) = (0, 0);
+                    let ret = (#function)(self.as_raw() as _, &mut lower, &mut upper);
+                    #resume
+                    if ret {
+                        (lower, ::core::option::Option::Some(upper))
+                    } else {
+                        (lower, ::core::option::Option::None)
+                    }
+                },
+            ),
+            Self::SizeHint => (
+                quote! { fn size_hint(&self) -> (usize, ::core::option::Option<usize>) },
+                quote! {
+                    let ret = (#function)(self.as_raw() as _);
+                    #resume
+                    ret
+                },
+            ),
+            Self::Read => (
+                quote! { fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> },
+                if foreign {
+                    lift_io_result(
+                        &function,
+                        slot_abi,
+                        quote! { let mut read = 0; },
+                        quote! { buf.as_mut_ptr(), buf.len(), &mut read, },
+                        &resume,
+                        quote! { read },
+                    )
+                } else {
+                    quote! {
+                        let ret = (#function)(self.as_raw() as _, buf);
+                        #resume
+                        ret
+                    }
+                },
+            ),
+            Self::Write => (
+                quote! { fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> },
+                if foreign {
+                    lift_io_result(
+                        &function,
+                        slot_abi,
+                        quote! { let mut written = 0; },
+                        quote! { buf.as_ptr(), buf.len(), &mut written, },
+                        &resume,
+                        quote! { written },
+                    )
+                } else {
+                    quote! {
+                        let ret = (#function)(self.as_raw() as _, buf);
+                        #resume
+                        ret
+                    }
+                },
+            ),
+            Self::Flush => (
+                quote! { fn flush(&mut self) -> ::std::io::Result<()> },
+                if foreign {
+                    lift_io_result(
+                        &function,
+                        slot_abi,
+                        quote! {},
+                        quote! {},
+                        &resume,
+                        quote! { () },
+                    )
+                } else {
+                    quote! {
+                        let ret = (#function)(self.as_raw() as _);
+                        #resume
+                        ret
+                    }
+                },
+            ),
+            Self::Seek => (
+                quote! { fn seek(&mut self, pos: ::std::io::SeekFrom) -> ::std::io::Result<u64> },
+                if foreign {
+                    lift_io_result(
+                        &function,
+                        slot_abi,
+                        quote! {
+                            let (whence, offset) = match pos {
+                                ::std::io::SeekFrom::Start(offset) => (0, offset as i64),
+                                ::std::io::SeekFrom::Current(offset) => (1, offset),
+                                ::std::io::SeekFrom::End(offset) => (2, offset),
+                            };
+                            let mut position = 0;
+                        },
+                        quote! { whence, offset, &mut position, },
+                        &resume,
+                        quote! { position },
+                    )
+                } else {
+                    quote! {
+                        let ret = (#function)(self.as_raw() as _, pos);
+                        #resume
+                        ret
+                    }
+                },
+            ),
+            Self::FillBuf => (
+                quote! { fn fill_buf(&mut self) -> ::std::io::Result<&[u8]> },
+                if foreign {
+                    lift_io_result(
+                        &function,
+                        slot_abi,
+                        quote! {
+                            let mut ptr = ::core::ptr::null();
+                            let mut len = 0;
+                        },
+                        quote! { &mut ptr, &mut len, },
+                        &resume,
+                        message.clone(),
+                    )
+                } else {
+                    quote! {
+                        let ret = (#function)(self.as_raw() as _);
+                        #resume
+                        ret.map(|buf| &*buf)
+                    }
+                },
+            ),
+            Self::Consume => (
+                quote! { fn consume(&mut self, amt: usize) },
+                quote! {
+                    (#function)(self.as_raw() as _, amt);
+                    #resume
+                },
+            ),
+            Self::Source => (
+                quote! {
+                    fn source(&self) -> ::core::option::Option<&(dyn ::std::error::Error + 'static)>
+                },
+                quote! {
+                    let ret = (#function)(self.as_raw() as _);
+                    #resume
+                    ret.map(|source| &*source)
+                },
+            ),
+        };
+        quote! {
+            #signature {
+                unsafe {
+                    #body
+                }
+            }
+        }
+    }
+}
+
+/// The kinds of I/O errors which are passed through vtable entries with a foreign ABI, numbered from 1. Other kinds are passed as `Other`, which is numbered 0.
+const IO_ERROR_KINDS: &[&str] = &[
+    "NotFound",
+    "PermissionDenied",
+    "ConnectionRefused",
+    "ConnectionReset",
+    "ConnectionAborted",
+    "NotConnected",
+    "AddrInUse",
+    "AddrNotAvailable",
+    "BrokenPipe",
+    "AlreadyExists",
+    "WouldBlock",
+    "InvalidInput",
+    "InvalidData",
+    "TimedOut",
+    "WriteZero",
+    "Interrupted",
+    "UnexpectedEof",
+];
+
+/// Converts a pointer and a length coming from foreign code into a slice. Empty slices may be passed with a null pointer, which `from_raw_parts` does not accept.
+fn slice_from_foreign(ptr: &TokenStream, len: &TokenStream, mutable: bool) -> TokenStream {
+    if mutable {
+        quote! {
+            if #ptr.is_null() || #len == 0 {
+                &mut []
+            } else {
+                ::core::slice::from_raw_parts_mut(#ptr, #len)
+            }
+        }
+    } else {
+        quote! {
+            if #ptr.is_null() || #len == 0 {
+                &[]
+            } else {
+                ::core::slice::from_raw_parts(#ptr, #len)
+            }
+        }
+    }
+}
+
+fn io_error_kinds() -> impl Iterator<Item = (Literal, Ident)> {
+    IO_ERROR_KINDS.iter().enumerate().map(|(i, kind)| {
+        (
+            Literal::i32_unsuffixed(i as i32 + 1),
+            Ident::new(kind, Span::call_site()),
+        )
+    })
+}
+
+/// Turns an `io::Result` into the return value of a vtable entry with a foreign ABI, running `on_ok` with the success value bound to `value`, or passing the error to the `report_error` callback in `error_args` along with its context. The error code is the OS error code if there is one, or the negated number of the error kind otherwise.
+fn report_io_error(
+    result: TokenStream,
+    on_ok: TokenStream,
+    error_args: &[TokenStream],
+) -> TokenStream {
+    let (report_error, context) = (&error_args[0], &error_args[1]);
+    let kinds = io_error_kinds().map(|(code, kind)| {
+        quote! { ::std::io::ErrorKind::#kind => -#code }
+    });
+    quote! {
+        match #result {
+            ::std::result::Result::Ok(value) => {
+                #on_ok
+                true
+            }
+            ::std::result::Result::Err(io_error) => {
+                let code = match io_error.raw_os_error() {
+                    ::std::option::Option::Some(code) if code > 0 => code,
+                    _ => match io_error.kind() {
+                        #(#kinds,)*
+                        _ => 0,
+                    },
+                };
+                let message = ::std::string::ToString::to_string(&io_error);
+                (#report_error)(#context, code, message.as_ptr(), message.len());
+                false
+            }
+        }
+    }
+}
+
+/// Calls `function`, a vtable entry with a foreign ABI, with `args`, each followed by a comma, and a callback which stores the reported error, and turns the result into an `io::Result`, with `ok` as the success value.
+fn lift_io_result(
+    function: &TokenStream,
+    slot_abi: Option<&Abi>,
+    setup: TokenStream,
+    args: TokenStream,
+    resume: &TokenStream,
+    ok: TokenStream,
+) -> TokenStream {
+    let kinds = io_error_kinds().map(|(code, kind)| {
+        quote! { #code => ::std::io::ErrorKind::#kind }
+    });
+    let message = slice_from_foreign(&quote! { ptr }, &quote! { len }, false);
+    quote! {
+        unsafe #slot_abi fn report_error(
+            context: *mut ::core::ffi::c_void,
+            code: i32,
+            ptr: *const u8,
+            len: usize,
+        ) {
+            let error = &mut *(context as *mut ::std::option::Option<::std::io::Error>);
+            *error = ::std::option::Option::Some(if code > 0 {
+                ::std::io::Error::from_raw_os_error(code)
+            } else {
+                let kind = match -code {
+                    #(#kinds,)*
+                    _ => ::std::io::ErrorKind::Other,
+                };
+                let message = #message;
+                ::std::io::Error::new(kind, ::std::string::String::from_utf8_lossy(message).into_owned())
+            });
+        }
+        #setup
+        let mut io_error = ::std::option::Option::None;
+        let ret = (#function)(
+            self.as_raw() as _,
+            #args
+            report_error,
+            &mut io_error as *mut ::std::option::Option<::std::io::Error> as *mut ::core::ffi::c_void,
+        );
+        #resume
+        if ret {
+            ::std::result::Result::Ok(#ok)
+        } else {
+            ::std::result::Result::Err(io_error.unwrap_or_else(|| {
+                ::std::io::Error::new(::std::io::ErrorKind::Other, "unknown error")
+            }))
+        }
+    }
+}
+
+/// Finds the supertraits of the trait which can be forwarded, along with those which they need the thin trait object to implement.
+pub fn forwarded_traits(
+    supertraits: &Punctuated<TypeParamBound, token::Add>,
+) -> syn::Result<Vec<ForwardedTrait>> {
+    let has_static_bound = supertraits.iter().any(
+        |supertrait| matches!(supertrait, TypeParamBound::Lifetime(lifetime) if lifetime.ident == "static"),
+    );
+    let mut forwarded_traits = Vec::new();
+    for supertrait in supertraits {
+        let bound = match supertrait {
+            TypeParamBound::Trait(bound) => bound,
+            TypeParamBound::Lifetime(..) => continue,
+        };
+        let forwarded_trait = match ForwardedTrait::from_bound(bound)? {
+            Some(forwarded_trait) => forwarded_trait,
+            None => continue,
+        };
+        let with_supertraits = forwarded_trait.with_supertraits();
+        if with_supertraits.contains(&ForwardedTrait::PartialEq) && !has_static_bound {
+            return Err(syn::Error::new_spanned(
+                bound,
+                "\
+forwarding comparison traits needs a `'static` bound on the trait, since the types of the values \
+are compared by their `TypeId`",
+            ));
+        }
+        for forwarded_trait in with_supertraits {
+            if !forwarded_traits.contains(&forwarded_trait) {
+                forwarded_traits.push(forwarded_trait);
+            }
+        }
+    }
+    Ok(forwarded_traits)
+}
+
+/// Creates the vtable entries for the forwarded supertraits, with the specified ABI.
+pub fn forwarded_items(
+    forwarded_traits: &[ForwardedTrait],
+    slot_abi: Option<&Abi>,
+) -> Vec<VtableItem> {
+    let mut methods = Vec::new();
+    for forwarded_trait in forwarded_traits {
+        for &method in forwarded_trait.methods() {
+            // `type_id` is shared by `Any` and the comparison traits.
+            if !methods.iter().any(|&(other, _)| other == method) {
+                methods.push((method, forwarded_trait));
+            }
+        }
+    }
+    methods
+        .into_iter()
+        .map(|(method, forwarded_trait)| method.vtable_item(forwarded_trait, slot_abi))
+        .collect()
+}
+
+/// Implements the forwarded supertraits on the thin trait object, which is `implementor` with the lifetime elided and the generic parameters from `generics`.
+pub fn generate_forwarded_impls(
+    forwarded_traits: &[ForwardedTrait],
+    items: &[VtableItem],
+    repr_name: &Ident,
+    generics: TokenStream,
+    implementor: TokenStream,
+    panic_policy: Option<PanicPolicy>,
+    trait_name: &Ident,
+) -> TokenStream {
+    let cross_type_order = forwarded_traits.contains(&ForwardedTrait::Ord);
+    let impls = forwarded_traits.iter().map(|forwarded_trait| {
+        let methods = items
+            .iter()
+            .filter(|item| {
+                matches!(item.forwarded, Some(method) if forwarded_trait.methods().contains(&method))
+            })
+            .map(|item| {
+                let name = &item.name;
+                let resume = resume_stored_panic(
+                    panic_policy,
+                    item.abi.as_ref(),
+                    item.effective_slot_abi(),
+                    trait_name,
+                );
+                item.forwarded.unwrap().impl_method(
+                    item,
+                    quote! { self.vtable().#name },
+                    resume,
+                    cross_type_order,
+                )
+            });
+        if *forwarded_trait == ForwardedTrait::Any {
+            return generate_downcasts(repr_name, &generics, &implementor, trait_name);
+        }
+        let path = forwarded_trait.path();
+        let associated_items = forwarded_trait.associated_items();
+        quote! {
+            impl #generics #path for #implementor {
+                #associated_items
+                #(#methods)*
+            }
+        }
+    });
+    quote! { #(#impls)* }
+}
+
+/// Generates the inherent methods for downcasting the thin trait object of a trait with `Any` as a supertrait to the types implementing the trait.
+fn generate_downcasts(
+    repr_name: &Ident,
+    generics: &TokenStream,
+    implementor: &TokenStream,
+    trait_name: &Ident,
+) -> TokenStream {
+    quote! {
+        impl #generics #implementor {
+            /// Returns `true` if the contained value is of type `T`.
+            #[inline]
+            pub fn is<T: #trait_name>(&self) -> bool {
+                let type_id = unsafe { (self.vtable().type_id)(self.as_raw() as _) };
+                type_id == ::core::any::TypeId::of::<T>()
+            }
+            /// Returns a reference to the contained value if it is of type `T`, or `None` if it isn't.
+            #[inline]
+            pub fn downcast_ref<T: #trait_name>(&self) -> ::core::option::Option<&T> {
+                if self.is::<T>() {
+                    let repr = self.as_raw() as *const #repr_name<T>;
+                    ::core::option::Option::Some(unsafe { &(*repr).__thintraitobjectmacro_repr_value })
+                } else {
+                    ::core::option::Option::None
+                }
+            }
+            /// Returns a mutable reference to the contained value if it is of type `T`, or `None` if it isn't.
+            #[inline]
+            pub fn downcast_mut<T: #trait_name>(&mut self) -> ::core::option::Option<&mut T> {
+                if self.is::<T>() {
+                    let repr = self.as_raw() as *mut #repr_name<T>;
+                    ::core::option::Option::Some(unsafe {
+                        &mut (*repr).__thintraitobjectmacro_repr_value
+                    })
+                } else {
+                    ::core::option::Option::None
+                }
+            }
+        }
+    }
+}
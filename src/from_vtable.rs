@@ -0,0 +1,330 @@
+//! The main body of the `#[thin_trait_object_from_vtable]` attribute macro, which goes in the opposite direction compared to `#[thin_trait_object]`: it takes an existing vtable struct, such as one generated by `bindgen` from a C header, and produces a trait along with a thin trait object which use that vtable.
+
+use super::{attr::*, options::*, repr::*, trait_object::*, unwind::*, vtable::*};
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use std::iter;
+use syn::{
+    parse::Parser,
+    punctuated::Punctuated,
+    BareFnArg,
+    Fields,
+    GenericArgument,
+    ItemStruct,
+    PathArguments,
+    Receiver,
+    ReturnType,
+    Type,
+    TypeBareFn,
+};
+
+pub fn from_vtable_main(attr: TokenStream, item: TokenStream) -> Result<TokenStream, syn::Error> {
+    let options = Punctuated::parse_terminated.parse2(attr)?;
+    reject_options(&options, |option| match option {
+        AttrOption::Vtable { .. } => {
+            Some("the vtable struct is the item on which the attribute is placed")
+        }
+        AttrOption::Abi { .. } => Some("the ABIs are taken from the types of the fields"),
+        AttrOption::DropAbi { .. } => {
+            Some("the ABI of the destructor is taken from the type of its field")
+        }
+        AttrOption::MarkerTraits { .. } => {
+            Some("the generated trait does not have any supertraits to be treated as markers")
+        }
+        AttrOption::StoreLayout { .. } => {
+            Some("cannot add the `size` and `align` fields to an existing vtable struct")
+        }
+        AttrOption::BlanketImpl { .. } => {
+            Some("the generated trait has methods of its own, taken from the vtable")
+        }
+        AttrOption::DynConversions { .. } => {
+            Some("the methods of the generated trait cannot be forwarded to `Box<dyn Trait>`")
+        }
+        AttrOption::LowerResults { .. }
+        | AttrOption::Marshal { .. }
+        | AttrOption::ThinTraits { .. } => {
+            Some("the signatures of the methods are taken from the types of the fields as-is")
+        }
+        _ => None,
+    })?;
+    let config = Config::from(options);
+    let ItemStruct {
+        vis: vtable_visibility,
+        ident: vtable_name,
+        generics,
+        fields,
+        ..
+    } = syn::parse2(item)?;
+    if !generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            generics.params,
+            "generic vtable structs are not supported by #[thin_trait_object_from_vtable]",
+        ));
+    }
+    let fields = match fields {
+        Fields::Named(fields) => fields.named,
+        other => {
+            return Err(syn::Error::new_spanned(
+                other,
+                "expected a vtable struct with named fields",
+            ))
+        }
+    };
+
+    let drop_name = config.drop_slot.unwrap_or_else(|| DropSlot::default().name);
+    let mut drop_slot = None;
+    let mut vtable_items = Vec::with_capacity(fields.len());
+    let mut trait_items = TokenStream::new();
+    for field in fields {
+        // Named fields always have a name.
+        let name = field.ident.unwrap();
+        let (function, nullable) = field_type_to_function(field.ty)?;
+        if name == drop_name {
+            drop_slot = Some(function_to_drop_slot(name, function, nullable)?);
+        } else {
+            // Carry the documentation over to the trait method, since that's where users will
+            // be looking for it when implementing the trait.
+            let docs = field
+                .attrs
+                .into_iter()
+                .filter(|attr| attr.path.is_ident("doc"));
+            let mut item = function_to_vtable_item(name, function, nullable)?;
+            // Without this, the trait methods would abort on panic by themselves, and the
+            // panic policy would never get the chance to kick in.
+            if config.panic_policy.is_some() {
+                if let Some(method_abi) = item.abi.as_ref().and_then(with_unwind) {
+                    item.slot_abi = item.abi.replace(method_abi);
+                }
+            }
+            let signature = item
+                .clone()
+                .into_signature(|x| format_ident!("arg{}", x + 1));
+            trait_items.extend(quote! {
+                #(#docs)*
+                #signature;
+            });
+            vtable_items.push(item);
+        }
+    }
+    let drop_slot = drop_slot.ok_or_else(|| {
+        syn::Error::new_spanned(
+            &vtable_name,
+            format!(
+                "\
+the vtable struct has no `{}` field, use `drop_slot = ...` to specify the destructor field",
+                drop_name,
+            ),
+        )
+    })?;
+
+    let trait_name = match config.trait_def_name {
+        Some(name) => name,
+        None => trait_name_from_vtable_name(&vtable_name)?,
+    };
+    let trait_visibility = config
+        .trait_def_visibility
+        .unwrap_or_else(|| vtable_visibility.clone());
+    let trait_attributes = config.trait_def_attributes;
+    let trait_def = quote! {
+        #(#trait_attributes)*
+        #trait_visibility trait #trait_name {
+            #trait_items
+        }
+    };
+
+    let trait_object_name = config
+        .trait_object_name
+        .unwrap_or_else(|| format_ident!("Boxed{}", &trait_name));
+    let mut stash = StageStash {
+        trait_name: trait_name.clone(),
+        vtable_name: vtable_name.clone(),
+        trait_object_name,
+        repr_name: repr_name_from_trait_name(trait_name.clone()),
+        prefix_name: prefix_name_from_trait_name(trait_name.clone()),
+        vtable_items,
+        drop_slot,
+        header: config.header,
+        panic_policy: config.panic_policy,
+        forwarded_traits: Vec::new(),
+        runtime: config.runtime,
+        stack_objects: config.stack_objects,
+        inline_storage: config.inline_storage,
+    };
+    let repr = generate_repr(
+        &mut stash,
+        config.inline_vtable,
+        path_to_box(),
+        config.allocator.as_ref(),
+        false,
+    );
+    let trait_object = generate_trait_object(
+        &mut stash,
+        config
+            .trait_object_visibility
+            .unwrap_or_else(|| trait_visibility.clone()),
+        config.inline_vtable,
+        false,
+        false,
+        &config.trait_object_attributes,
+        iter::empty(),
+    )?;
+    let repr_name = &stash.repr_name;
+    let vtable_constructor = quote! {
+        impl #vtable_name {
+            /// Returns the vtable which dispatches to the implementation of the trait on the specified type.
+            #[inline]
+            #vtable_visibility fn for_type<T: #trait_name + Sized>() -> Self {
+                #repr_name::<T>::__THINTRAITOBJECTMACRO_VTABLE
+            }
+        }
+    };
+    let ffi_checks = if config.strict_ffi {
+        generate_ffi_checks(&stash)
+    } else {
+        quote! {}
+    };
+    // Same as with the main macro, the vtable struct itself is added by the public wrapper.
+    let output = quote! {
+        #trait_def #repr #trait_object #vtable_constructor #ffi_checks
+    };
+    Ok(output)
+}
+
+/// Extracts the function pointer type from the type of a vtable field, also returning whether it was wrapped in an `Option`, which is how `bindgen` represents C function pointers.
+fn field_type_to_function(ty: Type) -> syn::Result<(TypeBareFn, bool)> {
+    let error_message = "expected a function pointer or an `Option` of one";
+    match ty {
+        Type::BareFn(function) => Ok((function, false)),
+        Type::Path(path) if path.qself.is_none() => {
+            let segment = path.path.segments.last().filter(|x| x.ident == "Option");
+            if let Some(PathArguments::AngleBracketed(args)) = segment.map(|x| &x.arguments) {
+                if let (1, Some(GenericArgument::Type(Type::BareFn(function)))) =
+                    (args.args.len(), args.args.first())
+                {
+                    return Ok((function.clone(), true));
+                }
+            }
+            Err(syn::Error::new_spanned(path, error_message))
+        }
+        other => Err(syn::Error::new_spanned(other, error_message)),
+    }
+}
+
+/// Checks the parts of a vtable function pointer which are common for the destructor and the methods, returning the type of the raw receiver.
+fn check_function(function: &TypeBareFn) -> syn::Result<Type> {
+    if function.unsafety.is_none() {
+        return Err(syn::Error::new_spanned(
+            function,
+            "\
+function pointers in the vtable must be `unsafe`, since they are given a raw pointer to the object",
+        ));
+    }
+    if let Some(variadic) = &function.variadic {
+        return Err(syn::Error::new_spanned(
+            variadic,
+            "variadic functions cannot be turned into trait methods",
+        ));
+    }
+    match function.inputs.first() {
+        Some(BareFnArg {
+            ty: ty @ Type::Ptr(..),
+            ..
+        }) => Ok(ty.clone()),
+        Some(other) => Err(syn::Error::new_spanned(
+            other,
+            "the first argument must be a raw pointer to the object",
+        )),
+        None => Err(syn::Error::new_spanned(
+            function,
+            "expected at least one argument, a raw pointer to the object",
+        )),
+    }
+}
+
+fn function_to_drop_slot(
+    name: Ident,
+    function: TypeBareFn,
+    nullable: bool,
+) -> syn::Result<DropSlot> {
+    let raw_receiver = check_function(&function)?;
+    if function.inputs.len() != 1 || function.output != ReturnType::Default {
+        return Err(syn::Error::new_spanned(
+            function,
+            "the destructor must take a raw pointer to the object and return nothing",
+        ));
+    }
+    Ok(DropSlot {
+        name,
+        abi: function.abi,
+        raw_receiver: Some(raw_receiver),
+        nullable,
+    })
+}
+
+fn function_to_vtable_item(
+    name: Ident,
+    function: TypeBareFn,
+    nullable: bool,
+) -> syn::Result<VtableItem> {
+    let raw_receiver = check_function(&function)?;
+    let mut inputs = function.inputs.into_iter();
+    // `*const` is `&self`, `*mut` is `&mut self`.
+    let receiver = match inputs.next() {
+        Some(BareFnArg {
+            attrs,
+            ty: Type::Ptr(pointer),
+            ..
+        }) => Receiver {
+            attrs,
+            reference: Some((Default::default(), None)),
+            mutability: pointer.mutability,
+            self_token: Default::default(),
+        },
+        _ => unreachable!("checked by check_function"),
+    };
+    let inputs = iter::once(VtableFnArg::Receiver(receiver))
+        .chain(inputs.map(|mut arg| {
+            // A `_` name is fine for a function pointer, but not for a trait method.
+            if arg.name.as_ref().map(|(name, _)| name == "_") == Some(true) {
+                arg.name = None;
+            }
+            VtableFnArg::Normal(arg)
+        }))
+        .collect();
+    Ok(VtableItem {
+        lifetimes: function.lifetimes.unwrap_or_default(),
+        // The trait methods are safe, since the receiver is a reference rather than a pointer.
+        unsafety: None,
+        abi: function.abi,
+        name,
+        inputs,
+        variadic: None,
+        output: function.output,
+        raw_receiver: Some(raw_receiver),
+        nullable,
+        slot_abi: None,
+        lowered_result: None,
+        marshalled_args: Vec::new(),
+        marshalled_output: None,
+        closure: None,
+        forwarded: None,
+    })
+}
+
+/// Turns `FooVtable` or `FooOps` into `Foo`.
+fn trait_name_from_vtable_name(vtable_name: &Ident) -> syn::Result<Ident> {
+    let name = vtable_name.to_string();
+    for suffix in &["Vtable", "VTable", "Ops"] {
+        if name.len() > suffix.len() && name.ends_with(suffix) {
+            return Ok(Ident::new(
+                &name[..name.len() - suffix.len()],
+                vtable_name.span(),
+            ));
+        }
+    }
+    Err(syn::Error::new_spanned(
+        vtable_name,
+        "\
+cannot infer the trait name from the vtable name, use `trait_def(...)` to specify it explicitly",
+    ))
+}
@@ -92,7 +92,28 @@
 //!   # trait MyTrait {}
 //!   ```
 //!   
-//! - `drop_abi = "..."` — specifies the ABI (the `"C"` in `extern "C"`) for the `drop` function pointer in the vtable. The ABI for all other methods in the vtable can be specified in the trait definition directly.
+//! - `abi = "..."` — specifies the ABI (the `"C"` in `extern "C"`) for the vtable entries of all methods which are declared without an `extern` qualifier in the trait definition. The trait methods and their implementations keep the Rust ABI, and the vtable entries are thunks which bridge between the two, aborting the process if a method panics unless the `on_panic` option says otherwise. Also serves as the default for `drop_abi`. Not specified by default, meaning that the vtable entries have the same ABI as the methods.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   #[thin_trait_object(
+//!       abi = "C"
+//!   )]
+//!   trait Counter {
+//!       fn get(&self) -> u32; // No need for extern "C" here...
+//!   }
+//!   impl Counter for u32 {
+//!       fn get(&self) -> u32 { // ...or here
+//!           *self
+//!       }
+//!   }
+//!
+//!   let counter = BoxedCounter::new(5_u32);
+//!   let get: unsafe extern "C" fn(*mut std::ffi::c_void) -> u32 = counter.vtable().get;
+//!   assert_eq!(unsafe { get(counter.as_raw() as *mut _) }, 5);
+//!   ```
+//! - `drop_abi = "..."` — specifies the ABI for the `drop` function pointer in the vtable, overriding the one specified by `abi`.
 //!   
 //!   Example:
 //!   ```rust
@@ -134,9 +155,417 @@
 //!   )]
 //!   # trait MyTrait {}
 //!   ```
+//! - `header(<type>)` — specifies a type which is to be stored at the very start of the object, before the vtable pointer. This is useful for adopting C objects which embed the pointer to their dispatch table after some common fields, as in `struct file { int refs; const struct file_ops *ops; }`. The type should be `#[repr(C)]` if it is to be accessed by C code. No header is used by default.
+//!
+//!   When a header is used, the `new` constructor fills it out using its `Default` implementation, while `new_with_header` accepts it as an explicit argument. The constructors which use the default value for the header, such as `new` and `try_new`, are only available if the header type implements `Default`. The `header` and `header_mut` methods provide access to the header of an existing object, no matter whether it was created by Rust or C code. The pointers taken by `from_raw` and returned by `as_raw` and `into_raw` point to the header rather than to the vtable pointer.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   #[repr(C)]
+//!   #[derive(Default)]
+//!   struct FileHeader {
+//!       refs: i32,
+//!   }
+//!
+//!   #[thin_trait_object(
+//!       header(FileHeader)
+//!   )]
+//!   trait File {
+//!       fn size(&self) -> u64;
+//!   }
+//!   impl File for Vec<u8> {
+//!       fn size(&self) -> u64 {
+//!           self.len() as u64
+//!       }
+//!   }
+//!
+//!   let mut file = BoxedFile::new_with_header(FileHeader { refs: 1 }, vec![0; 16]);
+//!   file.header_mut().refs += 1;
+//!   assert_eq!(file.header().refs, 2);
+//!   assert_eq!(file.size(), 16);
+//!   ```
+//!
+//!   A header type without a `Default` implementation has to be passed explicitly:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   #[repr(C)]
+//!   struct DeviceHeader {
+//!       id: std::num::NonZeroU32,
+//!   }
+//!
+//!   #[thin_trait_object(
+//!       header(DeviceHeader)
+//!   )]
+//!   trait Device {
+//!       fn is_open(&self) -> bool;
+//!   }
+//!   impl Device for bool {
+//!       fn is_open(&self) -> bool {
+//!           *self
+//!       }
+//!   }
+//!
+//!   let id = std::num::NonZeroU32::new(7).unwrap();
+//!   let device = BoxedDevice::new_with_header(DeviceHeader { id }, true);
+//!   assert_eq!(device.header().id.get(), 7);
+//!   assert!(device.is_open());
+//!   ```
+//! - `on_panic = "..."` — specifies what happens when the implementation of a method panics while being called through a vtable entry which cannot unwind, i.e. one with a foreign ABI such as `"C"`. Without this option, unwinding into C code is not possible, so the vtable entry aborts the process with a guard that panics again while the panic unwinds. Requires the `std` feature. The supported values are:
+//!   - `"abort"` — aborts the process explicitly;
+//!   - `"catch_return_default"` — discards the panic and returns `Default::default()` from the vtable entry;
+//!   - `"catch_and_store"` — returns `Default::default()` like the above, but also stores the panic in a thread-local. Calling a method on the thin trait object resumes the stored panic once the call returns to Rust, and the `take_panic` associated function retrieves it if the method was called by C code instead.
+//!
+//!   A panic cannot leave a method declared as `extern "C"` either, so the vtable entry never sees it: on Rust 1.81 and newer, the process is aborted, while on older versions, unwinding out of such a method is undefined behavior. For this reason, methods which are to use the policy should either keep the Rust ABI and get their foreign one from the `abi` option, or be declared with the `-unwind` variant of the ABI, such as `extern "C-unwind"`: when this option is specified, such methods get vtable entries with the plain `"C"` ABI, which catch the panic. The destructor is always covered if `drop_abi` is a foreign one. With `#[thin_trait_object_from_vtable]`, the generated trait methods receive the `-unwind` ABI automatically. The `-unwind` ABIs, and thus `on_panic` with `#[thin_trait_object_from_vtable]`, require Rust 1.71 or newer, while the `abi` option works with any supported version.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   #[thin_trait_object(
+//!       abi = "C",
+//!       on_panic = "catch_and_store"
+//!   )]
+//!   trait Parser {
+//!       fn parse(&self, input: u32) -> u32;
+//!   }
+//!   struct Strict;
+//!   impl Parser for Strict {
+//!       fn parse(&self, input: u32) -> u32 {
+//!           assert!(input != 0, "zero is not allowed");
+//!           input
+//!       }
+//!   }
+//!
+//!   let parser = BoxedParser::new(Strict);
+//!   // What C code would see:
+//!   let parse = parser.vtable().parse;
+//!   assert_eq!(unsafe { parse(parser.as_raw() as *mut _, 0) }, 0);
+//!   assert!(BoxedParser::take_panic().is_some());
+//!   // Calls from Rust resume the panic:
+//!   let result = std::panic::catch_unwind(|| parser.parse(0));
+//!   assert!(result.is_err());
+//!   ```
+//! - `lower_results = <true/false>` — specifies whether methods returning `Result<T, E>`, which has no stable layout, should be lowered to C-style error codes in the vtable. A lowered vtable entry takes a `*mut T` out-pointer as its last argument (omitted if `T` is `()`) and returns an `i32`, which is `0` on success, in which case the success value has been written to the out-pointer. Any other value is an error code. Set to `false` by default.
+//!
+//!   The mapping between `E` and the error codes is supplied by implementing `From<E> for NonZeroI32` and `From<NonZeroI32> for E`. The thin trait object converts the error code back into a `Result`, so the method can be called from Rust as usual. Lowered vtable entries have the `"C"` ABI, unless the method already has a foreign ABI or the `abi` option is used, in which case that ABI is used. Only the `Result<T, E>` form is recognized, not aliases like `io::Result<T>`. If the `on_panic` option discards a panic, the error code is `i32::MIN`.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use std::num::NonZeroI32;
+//!
+//!   #[derive(Debug, PartialEq)]
+//!   enum LoadError {
+//!       NotFound,
+//!       Other(NonZeroI32),
+//!   }
+//!   impl From<LoadError> for NonZeroI32 {
+//!       fn from(error: LoadError) -> Self {
+//!           match error {
+//!               LoadError::NotFound => NonZeroI32::new(2).unwrap(),
+//!               LoadError::Other(code) => code,
+//!           }
+//!       }
+//!   }
+//!   impl From<NonZeroI32> for LoadError {
+//!       fn from(code: NonZeroI32) -> Self {
+//!           match code.get() {
+//!               2 => LoadError::NotFound,
+//!               _ => LoadError::Other(code),
+//!           }
+//!       }
+//!   }
+//!
+//!   #[thin_trait_object(
+//!       lower_results = true
+//!   )]
+//!   trait Loader {
+//!       fn load(&self, id: u32) -> Result<u64, LoadError>;
+//!   }
+//!   struct Empty;
+//!   impl Loader for Empty {
+//!       fn load(&self, _id: u32) -> Result<u64, LoadError> {
+//!           Err(LoadError::NotFound)
+//!       }
+//!   }
+//!
+//!   let loader = BoxedLoader::new(Empty);
+//!   assert_eq!(loader.load(1), Err(LoadError::NotFound));
+//!   // The vtable entry, as seen by C code:
+//!   let load: unsafe extern "C" fn(*mut std::ffi::c_void, u32, *mut u64) -> i32 =
+//!       loader.vtable().load;
+//!   let mut out = 0;
+//!   assert_eq!(unsafe { load(loader.as_raw() as *mut _, 1, &mut out) }, 2);
+//!   ```
+//! - `strict_ffi = <true/false>` — specifies whether the argument and return types of vtable entries with a foreign ABI should be checked for FFI-safety. The checks are performed by the `improper_ctypes` lint, which is denied for them, and the errors point at the offending types in the trait definition. The destructor is checked as well if `drop_abi` is a foreign one. Set to `false` by default.
+//!
+//!   Example:
+//!   ```compile_fail
+//!   # use thin_trait_object::*;
+//!   #[thin_trait_object(
+//!       strict_ffi = true
+//!   )]
+//!   trait Logger {
+//!       // error: `extern` block uses type `String`, which is not FFI-safe
+//!       extern "C" fn log(&self, message: String);
+//!   }
+//!   ```
+//! - `marshal = <true/false>` — specifies whether common Rust types with no stable layout should be converted into FFI-safe ones in vtable entries with a foreign ABI. The trait methods keep their signatures, and the conversion is performed by the thunks in the vtable and by the thin trait object. Set to `false` by default. The following types are marshalled:
+//!   - `&str`, `&[T]` and `&mut [T]` arguments are passed as two arguments: a pointer to the first element (`*const u8`, `*const T` and `*mut T` respectively) and the length. They are named after the original argument with the `_ptr` and `_len` suffixes. A `&str` coming from C code which is not valid UTF-8 is cut off before the first invalid byte, since panicking would unwind into C code and the string is borrowed rather than owned, which leaves no room for replacement characters.
+//!   - `Option<&T>` and `Option<&mut T>` arguments are passed as `*const T` and `*mut T`, with `None` being a null pointer.
+//!   - `String` and `Vec<T>` arguments and return values, including the success value of a `Result` lowered by the `lower_results` option, are passed as `{trait name}FfiBuffer<u8>` and `{trait name}FfiBuffer<T>` respectively. The buffer is a `#[repr(C)]` struct with the `ptr`, `len` and `capacity` fields, along with the `free` function pointer, which is to be called by whoever receives the buffer once they are done with it. C code passing a buffer to Rust can use any allocator, as long as `free` releases the memory accordingly. Invalid UTF-8 in a `String` coming from C code is replaced with `U+FFFD REPLACEMENT CHARACTER`, as done by `String::from_utf8_lossy`.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use std::ffi::c_void;
+//!
+//!   #[thin_trait_object(
+//!       abi = "C",
+//!       marshal = true
+//!   )]
+//!   trait Greeter {
+//!       fn greet(&self, name: &str) -> String;
+//!   }
+//!   struct Polite;
+//!   impl Greeter for Polite {
+//!       fn greet(&self, name: &str) -> String {
+//!           format!("Hello, {}!", name)
+//!       }
+//!   }
+//!
+//!   let greeter = BoxedGreeter::new(Polite);
+//!   assert_eq!(greeter.greet("Rust"), "Hello, Rust!");
+//!   // The vtable entry, as seen by C code:
+//!   let greet: unsafe extern "C" fn(*mut c_void, *const u8, usize) -> GreeterFfiBuffer<u8> =
+//!       greeter.vtable().greet;
+//!   let name = "C";
+//!   let buffer = unsafe { greet(greeter.as_raw() as *mut _, name.as_ptr(), name.len()) };
+//!   let greeting = unsafe { std::slice::from_raw_parts(buffer.ptr, buffer.len) };
+//!   assert_eq!(greeting, b"Hello, C!");
+//!   unsafe { (buffer.free)(buffer.ptr, buffer.len, buffer.capacity) };
+//!   ```
+//!
+//! - `thin_traits(...)` — specifies a comma-separated list of other traits annotated with `#[thin_trait_object]`, whose thin trait objects are to be passed through the vtable as a single `*mut ::core::ffi::c_void` pointer each, making it possible to build object graphs across the FFI boundary. Each entry is a path to a trait, optionally followed by `= <path>` if its thin trait object does not have the default name, as in `thin_traits(Bar, other::Baz = other::MyBaz)`. For a trait `Bar` in the list, the following types are converted in the methods of every vtable entry:
+//!   - `BoxedBar`, in argument and return position, is passed as the pointer returned by its `into_raw` method. Ownership of the object is transferred to the receiving side, which is responsible for dropping it.
+//!   - `&BoxedBar`, in argument position only, is passed as the pointer returned by its `as_raw` method. The object stays owned by the caller and is not dropped by the callee.
+//!   - `impl Bar`, in argument and return position, is boxed into a `BoxedBar` and then passed like one. The receiving side gets the `BoxedBar`, which implements `Bar`. Using `impl Trait` in the return type of a trait method requires Rust 1.75.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use std::ffi::c_void;
+//!
+//!   #[thin_trait_object]
+//!   trait Node {
+//!       fn weight(&self) -> u32;
+//!   }
+//!   impl Node for u32 {
+//!       fn weight(&self) -> u32 {
+//!           *self
+//!       }
+//!   }
+//!
+//!   #[thin_trait_object(
+//!       abi = "C",
+//!       thin_traits(Node)
+//!   )]
+//!   trait Graph {
+//!       fn add(&mut self, node: impl Node);
+//!       fn take_last(&mut self) -> BoxedNode<'static>;
+//!       fn weigh(&self, node: &BoxedNode<'_>) -> u32;
+//!   }
+//!   struct Nodes(Vec<BoxedNode<'static>>);
+//!   impl Graph for Nodes {
+//!       fn add(&mut self, node: impl Node) {
+//!           self.0.push(BoxedNode::new(node.weight()));
+//!       }
+//!       fn take_last(&mut self) -> BoxedNode<'static> {
+//!           self.0.pop().unwrap()
+//!       }
+//!       fn weigh(&self, node: &BoxedNode<'_>) -> u32 {
+//!           node.weight()
+//!       }
+//!   }
+//!
+//!   let mut graph = BoxedGraph::new(Nodes(Vec::new()));
+//!   graph.add(42);
+//!   let node = graph.take_last();
+//!   assert_eq!(graph.weigh(&node), 42);
+//!   // The vtable entry, as seen by C code:
+//!   let weigh: unsafe extern "C" fn(*mut c_void, *mut c_void) -> u32 = graph.vtable().weigh;
+//!   assert_eq!(unsafe { weigh(graph.as_raw() as *mut _, node.as_raw() as *mut _) }, 42);
+//!   ```
+//!
+//! - `allocator = <path>` — specifies a value implementing [`GlobalAlloc`], usually a `static`, which is used to allocate and free the thin trait objects instead of `Box`. The memory is released through the same allocator by the destructor in the vtable, so objects handed over to C code still get freed correctly. Other allocations, such as the buffers used by the `marshal` option, are not affected. Not specified by default, meaning that the global allocator is used.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use std::alloc::{GlobalAlloc, Layout, System};
+//!   use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//!   // A stand-in for a memory pool, keeping track of how much memory is in use.
+//!   struct Pool(AtomicUsize);
+//!   unsafe impl GlobalAlloc for Pool {
+//!       unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+//!           self.0.fetch_add(layout.size(), Ordering::SeqCst);
+//!           System.alloc(layout)
+//!       }
+//!       unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+//!           self.0.fetch_sub(layout.size(), Ordering::SeqCst);
+//!           System.dealloc(ptr, layout)
+//!       }
+//!   }
+//!   static GRAPHICS: Pool = Pool(AtomicUsize::new(0));
+//!
+//!   #[thin_trait_object(allocator = GRAPHICS)]
+//!   trait Shape {
+//!       fn area(&self) -> u32;
+//!   }
+//!   impl Shape for (u32, u32) {
+//!       fn area(&self) -> u32 {
+//!           self.0 * self.1
+//!       }
+//!   }
+//!
+//!   let shape = BoxedShape::new((2, 3));
+//!   assert_ne!(GRAPHICS.0.load(Ordering::SeqCst), 0);
+//!   assert_eq!(shape.area(), 6);
+//!   drop(shape);
+//!   assert_eq!(GRAPHICS.0.load(Ordering::SeqCst), 0);
+//!   ```
+//! - `blanket_impl = <true/false>` — specifies whether the trait should be implemented for all types which implement its supertraits, including the thin trait object if it implements all of them, instead of being implemented on the thin trait object. Only applicable to traits without items of their own, which are used as a combination of their supertraits, usually [forwarded ones](#forwarded-standard-library-traits). Set to `false` by default.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use std::io::{Read, Write};
+//!
+//!   #[thin_trait_object(blanket_impl = true)]
+//!   trait Stream: Read + Write + 'static {}
+//!
+//!   // No need to implement `Stream` for `Cursor`.
+//!   let mut stream = BoxedStream::new(std::io::Cursor::new(Vec::new()));
+//!   stream.write_all(b"Hello World!").unwrap();
+//!   ```
+//!
+//!   The [`thin_trait_object_runtime`] crate uses this option to provide ready-made thin trait objects for common standard library traits, such as `ThinRead` and `ThinError`, which can be shared between crates.
+//! - `runtime = <path>` — specifies the path to the [`thin_trait_object_runtime`] crate, usually just `thin_trait_object_runtime`, whose `ThinTraitObject` trait is then implemented for the thin trait object. The trait has the vtable struct as its `Vtable` associated type and the `from_raw`, `as_raw`, `into_raw` and `vtable` methods, which forward to the inherent ones, so that code such as containers and FFI helpers can work with any thin trait object. Not specified by default, in which case the runtime crate is not needed.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use thin_trait_object_runtime::ThinTraitObject;
+//!
+//!   #[thin_trait_object(runtime = thin_trait_object_runtime)]
+//!   trait Foo {
+//!       fn foo(&self) -> u32;
+//!   }
+//!   impl Foo for u32 {
+//!       fn foo(&self) -> u32 {
+//!           *self
+//!       }
+//!   }
+//!
+//!   fn call_first_entry<T: ThinTraitObject<Vtable = FooVtable>>(object: &T) -> u32 {
+//!       unsafe { (object.vtable().foo)(object.as_raw() as _) }
+//!   }
+//!   assert_eq!(call_first_entry(&BoxedFoo::new(42)), 42);
+//!   ```
+//! - `dyn_conversions = <true/false>` — specifies whether the thin trait object should implement `From<Box<dyn Foo>>` and have an `into_dyn` method which converts it into a `Box<dyn Foo>`, for code which is gradually moving from boxed `dyn` trait objects to thin ones. To make the former possible, the trait is implemented for `Box<dyn Foo>` by calling the methods on the contents of the box, which the thin trait object then stores as its value. Converting a box into a thin trait object thus allocates the thin trait object, which adds a second pointer indirection to every call, while `into_dyn` gives the original box back without allocating, unless the `inline_vtable` option is used. A thin trait object which was not created from a box is moved into a new box instead, and stored as its value. With the `header(...)` option, `From` uses the default value for the header, and is only implemented if the header type implements `Default`. Not applicable to thin closures, to methods which take pinned receivers, since `Box<dyn Foo>` is `Unpin` and pinning it does not pin the value inside, and to traits with the `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Hash`, `Error` or `Any` forwarded supertraits, which either cannot be used as `dyn` trait objects or are not implemented by boxes of them. Set to `false` by default.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   #[thin_trait_object(dyn_conversions = true)]
+//!   trait Foo {
+//!       fn foo(&self) -> u32;
+//!   }
+//!   impl Foo for u32 {
+//!       fn foo(&self) -> u32 {
+//!           *self
+//!       }
+//!   }
+//!
+//!   fn old_api() -> Box<dyn Foo> {
+//!       Box::new(42)
+//!   }
+//!   fn new_api(foo: BoxedFoo<'_>) -> u32 {
+//!       foo.foo()
+//!   }
+//!
+//!   assert_eq!(new_api(old_api().into()), 42);
+//!   let foo: Box<dyn Foo> = BoxedFoo::new(42).into_dyn();
+//!   assert_eq!(foo.foo(), 42);
+//!
+//!   // The box is given back as it was.
+//!   let boxed = old_api();
+//!   let address = &*boxed as *const dyn Foo as *const u8;
+//!   let foo = BoxedFoo::from(boxed).into_dyn();
+//!   assert_eq!(&*foo as *const dyn Foo as *const u8, address);
+//!   ```
+//!
+//!   There is no `From<T>` implementation for all types implementing the trait, since it would overlap with the `From<T> for T` one from the standard library — the `new` constructor serves that purpose instead.
+//! - `stack_objects = <true/false>` — specifies whether the `StackFoo<T>` and `MutFoo<'a>` structs should be generated, [as described below](#output-reference). A stack object stores the value along with the vtable pointer in the same layout as the allocation of a thin trait object, but without allocating, and can be borrowed as a `MutFoo`, which is a thin trait object that is one pointer wide and never frees the object. This allows passing short-lived objects to C code or to functions generic over the trait for the duration of a call. Not applicable to traits with methods which take pinned receivers and to `FnOnce` thin closures. Set to `false` by default.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   use std::ffi::c_void;
+//!
+//!   #[thin_trait_object(stack_objects = true, abi = "C")]
+//!   trait Counter {
+//!       fn increment(&mut self) -> u32;
+//!   }
+//!   impl Counter for u32 {
+//!       fn increment(&mut self) -> u32 {
+//!           *self += 1;
+//!           *self
+//!       }
+//!   }
+//!
+//!   // Stands in for a C function which takes the object and its vtable.
+//!   unsafe extern "C" fn increment_twice(object: *mut c_void, vtable: *const CounterVtable) -> u32 {
+//!       ((*vtable).increment)(object);
+//!       ((*vtable).increment)(object)
+//!   }
+//!
+//!   let mut counter = StackCounter::new(40_u32);
+//!   let thin = counter.as_thin();
+//!   assert_eq!(unsafe { increment_twice(thin.as_raw() as _, thin.vtable()) }, 42);
+//!   assert_eq!(counter.into_inner(), 42);
+//!   ```
+//! - `inline_storage = <true/false>` — specifies whether the `InlineFoo<N>` struct should be generated, [as described below](#output-reference). It is an owned trait object which stores values of up to `N` bytes inside of itself along with the vtable pointer, falling back to boxing larger ones, so that creating and dropping many small objects doesn't allocate. It is wider than a pointer, but a pointer to the object which can be used with the vtable can be obtained with `as_raw`. Not applicable to traits with methods which take pinned receivers and to `FnOnce` thin closures, the same as for `stack_objects`. Set to `false` by default.
+//!
+//!   Example:
+//!   ```rust
+//!   # use thin_trait_object::*;
+//!   #[thin_trait_object(inline_storage = true)]
+//!   trait Handler {
+//!       fn handle(&mut self, event: u32);
+//!   }
+//!   impl Handler for u32 {
+//!       fn handle(&mut self, event: u32) {
+//!           *self += event;
+//!       }
+//!   }
+//!   impl Handler for [u32; 16] {
+//!       fn handle(&mut self, event: u32) {
+//!           self[0] += event;
+//!       }
+//!   }
+//!
+//!   let mut small = InlineHandler::<8>::new(0_u32);
+//!   let mut large = InlineHandler::<8>::new([0_u32; 16]);
+//!   assert!(small.is_inline());
+//!   assert!(!large.is_inline());
+//!   small.handle(42);
+//!   large.handle(42);
+//!   ```
 //!
 //! ## Use with FFI
-//! One of the main focuses of the macro is FFI, which is why usage of the macro with FFI is simple and natural:
+//! One of the main focuses of the macro is FFI, which is why usage of the macro with FFI is simple and natural. The ABI of each method can be specified in the trait definition, as shown below, or for all methods at once with the `abi` option, which spares the implementations from having to repeat it:
 //! ```no_run
 //! use thin_trait_object::*;
 //! use std::ffi::c_void;
@@ -218,6 +647,82 @@
 //! }
 //! ```
 //!
+//! Many C libraries, such as event loops and parsers, accept callbacks in the form of a function pointer and a `void*` which is passed back to it as the first argument. Since methods with a non-Rust ABI already have that exact shape in the vtable, the thin trait object provides `as_c_callback_<method name>` and `into_c_callback_<method name>` for all such methods. The former borrows the object for as long as the callback is used, while the latter transfers ownership and additionally returns the destructor which the C side is to call once it is done with the callback:
+//! ```rust
+//! use thin_trait_object::*;
+//! use std::os::raw::{c_int, c_void};
+//!
+//! #[thin_trait_object(drop_abi = "C")]
+//! trait Handler {
+//!     extern "C" fn handle(&mut self, event: c_int);
+//! }
+//! impl Handler for Vec<c_int> {
+//!     extern "C" fn handle(&mut self, event: c_int) {
+//!         self.push(event);
+//!     }
+//! }
+//!
+//! # /*
+//! extern "C" {
+//!     fn run_event_loop(
+//!         callback: unsafe extern "C" fn(*mut c_void, c_int),
+//!         user_data: *mut c_void,
+//!     );
+//! }
+//! # */
+//! # unsafe extern "C" fn run_event_loop(
+//! #     callback: unsafe extern "C" fn(*mut c_void, c_int),
+//! #     user_data: *mut c_void,
+//! # ) {
+//! #     callback(user_data, 1);
+//! # }
+//! let mut handler = BoxedHandler::new(Vec::new());
+//! let (callback, user_data) = handler.as_c_callback_handle();
+//! unsafe { run_event_loop(callback, user_data) };
+//! ```
+//!
+//! ## Implementing existing vtables
+//! The FFI example above works best when the interface is designed on the Rust side. When the vtable is dictated by existing C code, as is the case with plugin ABIs of C programs, the `#[thin_trait_object_from_vtable]` attribute can be used instead. It is placed on the vtable struct, such as one generated by `bindgen`, and produces the matching trait, the thin trait object struct and a `for_type` constructor for the vtable:
+//! ```rust
+//! use thin_trait_object::*;
+//! use std::os::raw::c_int;
+//!
+//! #[thin_trait_object_from_vtable(drop_slot = release)]
+//! #[repr(C)]
+//! pub struct CounterOps {
+//!     /// Increments the counter by the specified amount.
+//!     pub add: Option<unsafe extern "C" fn(this: *mut CounterOps, amount: c_int)>,
+//!     pub get: Option<unsafe extern "C" fn(this: *const CounterOps) -> c_int>,
+//!     pub release: Option<unsafe extern "C" fn(this: *mut CounterOps)>,
+//! }
+//!
+//! // The trait is generated by the macro:
+//! // pub trait Counter {
+//! //     /// Increments the counter by the specified amount.
+//! //     extern "C" fn add(&mut self, amount: c_int);
+//! //     extern "C" fn get(&self) -> c_int;
+//! // }
+//! impl Counter for c_int {
+//!     extern "C" fn add(&mut self, amount: c_int) {
+//!         *self += amount;
+//!     }
+//!     extern "C" fn get(&self) -> c_int {
+//!         *self
+//!     }
+//! }
+//!
+//! let mut counter = BoxedCounter::new(0);
+//! counter.add(2);
+//! assert_eq!(counter.get(), 2);
+//! assert!(CounterOps::for_type::<c_int>().get.is_some());
+//! ```
+//! The fields are handled as follows:
+//! - Every field must be an `unsafe` function pointer, optionally wrapped in an `Option` (which is how `bindgen` represents nullable C function pointers), taking a raw pointer to the object as its first argument. The pointee type does not matter, but the mutability does: methods with a `*const` receiver take `&self`, while those with a `*mut` one take `&mut self`.
+//! - The field named `drop` is used as the destructor, which can be overriden with the `drop_slot = <field name>` option. The destructor must not take any other arguments or return anything.
+//! - All other fields become trait methods with the same name, ABI and arguments, and without the `unsafe` qualifier, retaining the documentation comments of the fields. A null function pointer in a vtable coming from C code results in a panic when the corresponding method is called.
+//!
+//! The name of the trait is inferred by removing the `Vtable` or `Ops` suffix from the name of the struct, and the visibility is taken from the struct. Those, along with attributes for the trait, can be specified with the `trait_def(<attributes> <visibility> <name>)` option, which has the same syntax as the `vtable(...)` one. The `trait_object(...)`, `inline_vtable`, `header(...)`, `on_panic`, `strict_ffi`, `allocator`, `runtime`, `stack_objects` and `inline_storage` options work the same way they do for the main macro, while the rest are not applicable.
+//!
 //! ## Supertraits
 //! Consider this situation:
 //! ```compile_fail
@@ -255,6 +760,173 @@
 //! This is necessary because the macro has no access to `A` and thus doesn't know that it needs to add its methods to the vtable.
 //! A little hacky, but there is no cleaner way of doing this using only procedural macros. If you have any suggestions for improving this pattern, raise an issue explaining your proposed solution or create a PR.
 //!
+//! ### Forwarded standard library traits
+//! Some traits from the standard library are known to the macro, which adds vtable entries for their methods and implements them on the thin trait object, so that no manual implementation is needed. They are recognized by their name, with or without the path of their module, as in `Debug`, `fmt::Debug` or `core::fmt::Debug`:
+//! - `Debug` and `Display`, with the `fmt_debug` and `fmt_display` entries respectively.
+//! - `PartialEq`, `Eq`, `PartialOrd`, `Ord` and `Hash`, with the `eq`, `partial_cmp`, `cmp` and `hash` entries. `Eq` has no methods and thus no entry, and the traits which those require, such as `PartialEq` for `Eq`, are forwarded as well. The comparison entries take a pointer to the other object, and are only called if it holds a value of the same type, which is checked by comparing the results of the `type_id` entry. This requires the trait to have a `'static` bound, as `TypeId` only exists for `'static` types. Objects with values of different types are unequal, and are ordered by their `TypeId` if `Ord` is forwarded, or are incomparable otherwise.
+//! - `Error`, with the `source` entry, along with `Debug` and `Display`, which it requires. The thin trait object can thus be returned as an error which is only one pointer wide, and converted into `Box<dyn Error>` by the `?` operator, or into `Box<dyn Error + Send + Sync>` if the trait has the `Send` and `Sync` bounds. Since `Error` is not available in `core`, this requires the standard library.
+//! - `Iterator<Item = T>`, with the `next` and `size_hint` entries, and with `T` as the `Item` type of the implementation on the thin trait object. The `Item` binding has to be specified in the supertrait.
+//! - `Any`, with the `type_id` entry, shared with the comparison traits. Since `Any` cannot be implemented manually, the thin trait object gets the `is`, `downcast_ref` and `downcast_mut` inherent methods instead, which work like those of `dyn Any`, but only for types implementing the trait.
+//! - `Read`, `Write`, `Seek` and `BufRead` from `std::io`, with the `read`, `write`, `flush`, `seek`, `fill_buf` and `consume` entries, as well as `Read` for `BufRead`, which requires it. Only the required methods of those traits get entries, while the provided ones, like `read_to_end`, use their default implementations. A bare `Write` is taken to mean `io::Write` rather than `fmt::Write`. Those require the standard library as well.
+//!
+//! ```rust
+//! use std::fmt::{self, Debug};
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object]
+//! trait Shape: Debug + fmt::Display {
+//!     fn area(&self) -> u32;
+//! }
+//!
+//! #[derive(Debug)]
+//! struct Square(u32);
+//! impl fmt::Display for Square {
+//!     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+//!         write!(f, "a square with a side of {}", self.0)
+//!     }
+//! }
+//! impl Shape for Square {
+//!     fn area(&self) -> u32 {
+//!         self.0 * self.0
+//!     }
+//! }
+//!
+//! let shape = BoxedShape::new(Square(2));
+//! assert_eq!(format!("{:?}", shape), "Square(2)");
+//! assert_eq!(shape.to_string(), "a square with a side of 2");
+//! ```
+//! With the comparison traits, thin trait objects can be keys in maps and sets, even with values of different types:
+//! ```rust
+//! use std::{collections::BTreeSet, fmt::Debug};
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object]
+//! trait Key: Debug + Ord + 'static {}
+//! impl Key for u32 {}
+//! impl Key for String {}
+//!
+//! let mut keys = BTreeSet::new();
+//! keys.insert(BoxedKey::new(2_u32));
+//! keys.insert(BoxedKey::new("two".to_string()));
+//! keys.insert(BoxedKey::new(2_u32));
+//! assert_eq!(keys.len(), 2);
+//! assert!(keys.contains(&BoxedKey::new("two".to_string())));
+//! assert!(BoxedKey::new(2_u32) != BoxedKey::new("2".to_string()));
+//! ```
+//! An error type which is as cheap to return as a reference:
+//! ```rust
+//! use std::{error::Error, fmt, mem};
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object]
+//! trait ParseError: Error + Send + Sync {}
+//!
+//! #[derive(Debug)]
+//! struct UnexpectedEnd;
+//! impl fmt::Display for UnexpectedEnd {
+//!     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+//!         f.write_str("unexpected end of input")
+//!     }
+//! }
+//! impl Error for UnexpectedEnd {}
+//! impl ParseError for UnexpectedEnd {}
+//!
+//! fn parse(input: &str) -> Result<u8, BoxedParseError<'static>> {
+//!     input.bytes().next().ok_or_else(|| BoxedParseError::new(UnexpectedEnd))
+//! }
+//! fn run() -> Result<u8, Box<dyn Error + Send + Sync>> {
+//!     Ok(parse("")?)
+//! }
+//!
+//! assert_eq!(mem::size_of::<Result<(), BoxedParseError>>(), mem::size_of::<usize>());
+//! assert_eq!(run().unwrap_err().to_string(), "unexpected end of input");
+//! ```
+//! A thin iterator:
+//! ```rust
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object]
+//! trait Countdown: Iterator<Item = u32> {}
+//!
+//! struct Count(u32);
+//! impl Iterator for Count {
+//!     type Item = u32;
+//!     fn next(&mut self) -> Option<u32> {
+//!         self.0 = self.0.checked_sub(1)?;
+//!         Some(self.0)
+//!     }
+//! }
+//! impl Countdown for Count {}
+//!
+//! let countdown = BoxedCountdown::new(Count(3));
+//! assert_eq!(countdown.collect::<Vec<_>>(), [2, 1, 0]);
+//! ```
+//! A storage backend with a file-like interface:
+//! ```rust
+//! use std::io::{self, Cursor, Read, Seek, SeekFrom};
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object(abi = "C")]
+//! trait Storage: Read + Seek {}
+//! impl Storage for Cursor<Vec<u8>> {}
+//!
+//! let mut storage = BoxedStorage::new(Cursor::new(b"Hello World!".to_vec()));
+//! storage.seek(SeekFrom::Start(6)).unwrap();
+//! let mut text = String::new();
+//! storage.read_to_string(&mut text).unwrap();
+//! assert_eq!(text, "World!");
+//! let error = storage.seek(SeekFrom::Current(-100)).unwrap_err();
+//! assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+//! ```
+//! The formatting entries take the `Formatter` if they have the Rust ABI. Since it cannot cross the FFI boundary, those with a foreign ABI instead take a callback with the same ABI which writes a UTF-8 string, `unsafe extern "C" fn(context: *mut c_void, ptr: *const u8, len: usize) -> bool`, along with the context pointer which is to be passed to it, and return whether formatting succeeded, as does the callback. Formatting options, such as width or the alternate flag, are not passed on in this case.
+//!
+//! The same goes for the `hash` entry, which takes `&mut dyn Hasher` with the Rust ABI, or a callback which writes bytes to the hasher, `unsafe extern "C" fn(context: *mut c_void, ptr: *const u8, len: usize)`, along with the context pointer otherwise. With a foreign ABI, `partial_cmp` and `cmp` return an `i8`, which is `-1`, `0` or `1` for `Less`, `Equal` and `Greater` respectively, and `2` if the values are incomparable. With a foreign ABI, `next` takes a pointer to which it writes the item, and returns whether there was one, and `size_hint` takes pointers for the lower and upper bounds, and returns whether there is an upper bound. The I/O entries take pointers and lengths instead of slices, write their results to the `out` pointers, and return whether the operation succeeded. The origin of `seek` is passed as an integer, which is `0`, `1` or `2` for the start, the current position and the end respectively, like `SEEK_SET`, `SEEK_CUR` and `SEEK_END` in C. Errors are reported by calling the `report_error` callback, `unsafe extern "C" fn(context: *mut c_void, code: i32, ptr: *const u8, len: usize)`, with the `context` pointer, an error code and a UTF-8 message, before returning `false`. The code is the OS error code, if the error has one and it is positive, or otherwise the negated number of the `ErrorKind` in the following list, starting from 1, with `0` for the rest: `NotFound`, `PermissionDenied`, `ConnectionRefused`, `ConnectionReset`, `ConnectionAborted`, `NotConnected`, `AddrInUse`, `AddrNotAvailable`, `BrokenPipe`, `AlreadyExists`, `WouldBlock`, `InvalidInput`, `InvalidData`, `TimedOut`, `WriteZero`, `Interrupted`, `UnexpectedEof`. The message is only used for the latter. Pointers which come from C code along with a length, such as the message, the bytes passed to the formatting and hashing callbacks and the buffers of the I/O entries, may be null if the length is 0. The `type_id` and `source` entries always have the Rust ABI, since `TypeId` and `dyn Error` have no stable layout.
+//!
+//! ## Thin closures
+//! The `Fn`, `FnMut` and `FnOnce` supertraits are an exception to the above: a trait which has one of them as a supertrait and no items of its own becomes a thin closure type, which is one pointer wide and can be passed to C code as a callback. `Box<dyn Fn(...)>`, in comparison, is two pointers wide and has no defined layout. The vtable gets a `call`, `call_mut` or `call_once` entry respectively, taking the arguments of the closure after the pointer to the object, and the macro implements the trait for all closures which implement its supertraits. Since the closure traits themselves cannot be implemented on stable Rust, the thin trait object has an inherent method with the same name instead:
+//! ```rust
+//! use std::os::raw::c_int;
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object(abi = "C")]
+//! trait Callback: Fn(c_int) -> c_int {}
+//!
+//! let offset = 2;
+//! let callback = BoxedCallback::new(move |x| x + offset);
+//! assert_eq!(callback.call(40), 42);
+//! // What gets passed to C code:
+//! let (call, user_data) = callback.as_c_callback_call();
+//! assert_eq!(unsafe { call(user_data, 40) }, 42);
+//! ```
+//! The `call_once` entry consumes the object: it moves the closure out of the allocation, frees it and then calls the closure, so the destructor must not be called on the object afterwards. The `as_c_callback_call_once` and `into_c_callback_call_once` methods are not generated for this reason.
+//!
+//! ## Pinned receivers
+//! Methods can take `self: Pin<&mut Self>` or `self: Pin<&Self>` besides `&self` and `&mut self`, which is what poll-style traits and self-referential types need. The value never moves out of its allocation, so it stays pinned for as long as the object exists, but the thin trait object could still hand out `&mut` references to it through the other methods. For this reason, the thin trait object does not implement [`Unpin`] if the trait has pinned receivers, and a `Pinned{trait name}` struct is generated as well, which is `Unpin` and calls the methods with pinned receivers through plain references instead:
+//! ```rust
+//! use std::pin::Pin;
+//! use thin_trait_object::*;
+//!
+//! #[thin_trait_object]
+//! trait Task {
+//!     fn poll(self: Pin<&mut Self>, budget: u32) -> bool;
+//! }
+//! struct Countdown(u32);
+//! impl Task for Countdown {
+//!     fn poll(mut self: Pin<&mut Self>, budget: u32) -> bool {
+//!         self.0 = self.0.saturating_sub(budget);
+//!         self.0 == 0
+//!     }
+//! }
+//!
+//! let mut tasks = vec![PinnedTask::new(Countdown(3)), PinnedTask::new(Countdown(1))];
+//! let finished = tasks.iter_mut().map(|task| task.poll(2)).collect::<Vec<_>>();
+//! assert_eq!(finished, [false, true]);
+//! // Moving the pinned thin trait object does not move the value.
+//! let mut task = tasks.remove(0);
+//! assert!(task.poll(2));
+//! ```
+//! Methods which take `&mut self` are not available on the pinned struct, since those could move the value out. A thin trait object can be turned into a pinned one with `From`.
+//!
 //! ## Output reference
 //! The following is a comprehensive list of everything the macro emits:
 //! - **The trait itself**, with all other attributes.
@@ -274,8 +946,12 @@
 //!   # */
 //!   ```
 //!   The other fields, ones besides `drop`, each have the same name as their corresponding trait method. The signatures are nearly identical, with two differences:
-//!   - `&self` or `&mut self`, if present, are replaced with [`*mut ::core::ffi::c_void`][`core::ffi::c_void`];
+//!   - `&self`, `&mut self`, `self: Pin<&Self>` or `self: Pin<&mut Self>`, if present, are replaced with [`*mut ::core::ffi::c_void`][`core::ffi::c_void`];
 //!   - If there was no `unsafe` on the trait method, it is added automatically, since the pointer passed as the first argument is never validated.
+//!
+//!   If the `abi`, `lower_results`, `marshal`, `thin_traits` or `on_panic` options are used, the ABI and signature of some entries are changed further, as described in the documentation for those options.
+//!
+//!   The entries for the methods of forwarded standard library supertraits, such as `fmt_debug`, come after the methods of the trait, as described in the [Supertraits](#forwarded-standard-library-traits) section.
 //! - **A thin trait object struct definition.**
 //!   
 //!   The name can be customized via the `trait_object(...)` configuration option (see the *Configuring the macro* section); the default name is `Boxed{trait name}`, as in, `BoxedFoo` for a trait named `Foo`.
@@ -290,7 +966,7 @@
 //!   );
 //!   # */
 //!   ```
-//!   If the trait has a `'static` lifetime bound, the `'inner` lifetime parameter is not emitted, since all possible contained implementations are restricted to be `'static`.
+//!   If the trait has a `'static` lifetime bound, the `'inner` lifetime parameter is not emitted, since all possible contained implementations are restricted to be `'static`. If the trait has methods with pinned receivers, the `PhantomData` also contains [`PhantomPinned`], so that the struct does not implement [`Unpin`].
 //!   
 //!   The following methods and associated functions are present on the boxed thin trait object structure:
 //!   - ```no_run
@@ -299,12 +975,27 @@
 //!     # */
 //!     ```
 //!     Constructs a boxed thin trait object from a type implementing the trait. The `'inner` bound is replaced with `'static` if the `'static` lifetime is one of the supertraits on the base trait.
+//!
+//!     If `T` is zero-sized, such as a unit struct, and the `header(...)` option is not used, nothing is allocated: the object then points to a constant containing only the vtable pointer (or the vtable itself, if `inline_vtable` is used), and the destructor drops the value without freeing anything. Zero-sized types with an alignment greater than that of the vtable pointer are still allocated.
+//!   - ```no_run
+//!     # /*
+//!     fn try_new<T: {trait name} + Sized + 'inner>(val: T) -> Result<Self, T>
+//!     # */
+//!     ```
+//!     Same as `new`, but gives the value back instead of aborting if the allocation fails.
+//!   - ```no_run
+//!     # /*
+//!     fn new_with<T: {trait name} + Sized + 'inner, F: FnOnce() -> T>(f: F) -> Self
+//!     unsafe fn new_in_place<T: {trait name} + Sized + 'inner>(init: impl FnOnce(&mut MaybeUninit<T>)) -> Self
+//!     # */
+//!     ```
+//!     Construct a thin trait object from a value which is produced once the allocation is made. `new_with` takes the return value of the closure, which gives the compiler the opportunity to write it directly into the allocation rather than copying it through the stack, although that is not guaranteed. `new_in_place` lets the closure initialize the [`MaybeUninit`] inside the allocation however it likes, which is how large values can be guaranteed to never be on the stack, and is unsafe because the value must be fully initialized once the closure returns. If the closure panics, the allocation is freed.
 //!   - ```no_run
 //!     # /*
 //!     const unsafe fn from_raw(ptr: *mut ()) -> Self
 //!     # */
 //!     ```
-//!     Creates a thin trait object directly from a raw pointer to its vtable.
+//!     Creates a thin trait object directly from a raw pointer to its vtable, or to its header if the `header(...)` option is used.
 //!
 //!     ### Safety
 //!     This constructor, by its nature, is hugely unsafe and should be avoided when possible. The following invariants must be upheld:
@@ -318,7 +1009,7 @@
 //!     const fn as_raw(&self) -> *mut ()
 //!     # */
 //!     ```
-//!     Extracts the contained pointer to the trait object.
+//!     Extracts the contained pointer to the trait object, which points to the header if the `header(...)` option is used.
 //!
 //!     Unlike `into_raw`, ownership of the pointer is not released, and as such will be dropped normally. Unless the original copy is removed via [`mem::forget`] or [`ManuallyDrop`], calling `from_raw` and then dropping will cause undefined behavior.
 //!   - ```no_run
@@ -335,13 +1026,82 @@
 //!     # */
 //!     ```
 //!     Retrieves the raw vtable of the contained trait object.
+//!   - ```no_run
+//!     # /*
+//!     fn new_with_header<T: {trait name} + Sized + 'inner>(header: {header type}, val: T) -> Self
+//!     fn try_new_with_header<T: {trait name} + Sized + 'inner>(header: {header type}, val: T) -> Result<Self, ({header type}, T)>
+//!     unsafe fn new_in_place_with_header<T: {trait name} + Sized + 'inner>(header: {header type}, init: impl FnOnce(&mut MaybeUninit<T>)) -> Self
+//!     fn header(&self) -> &{header type}
+//!     fn header_mut(&mut self) -> &mut {header type}
+//!     # */
+//!     ```
+//!     Only present if the `header(...)` option is used. Construct a thin trait object with an explicit header, giving both back if the allocation fails in the case of `try_new_with_header` or initializing the value in place in the case of `new_in_place_with_header`, and access the header of an existing one respectively.
+//!   - ```no_run
+//!     # /*
+//!     fn as_c_callback_{method name}(&self) -> ({vtable entry type}, *mut ::core::ffi::c_void)
+//!     fn into_c_callback_{method name}(self) -> ({vtable entry type}, *mut ::core::ffi::c_void, {drop entry type})
+//!     # */
+//!     ```
+//!     Only present for methods with an ABI other than `"Rust"`, and take `&mut self` instead of `&self` if the method does. Return the vtable entry for the method along with the pointer to the object which is to be passed to it, i.e. a callback and its user data in C terms. The `into_` version releases ownership of the object and also returns the destructor which is to be called with the pointer at a later time.
+//!   - ```no_run
+//!     # /*
+//!     fn take_panic() -> Option<Box<dyn Any + Send>>
+//!     # */
+//!     ```
+//!     Only present if the `on_panic = "catch_and_store"` option is used. Retrieves the panic caught by a vtable entry on the current thread, if any, removing it from storage.
+//!   - ```no_run
+//!     # /*
+//!     fn into_dyn(self) -> Box<dyn {trait name} + 'inner>
+//!     # */
+//!     ```
+//!     Only present if the `dyn_conversions = true` option is used, along with the `From<Box<dyn {trait name} + 'inner>>` implementation. Converts the thin trait object into a boxed `dyn` one, giving back the box which it was created from if there is one, or moving it into a new box otherwise. The former is recognized by the address of the vtable, which is why the box is always moved into a new one if the `inline_vtable` option is used.
+//!   - ```no_run
+//!     # /*
+//!     fn call(&self, ...) -> ...
+//!     fn call_mut(&mut self, ...) -> ...
+//!     fn call_once(self, ...) -> ...
+//!     # */
+//!     ```
+//!     Only present if the trait has an `Fn`, `FnMut` or `FnOnce` supertrait respectively, in which case the trait is not implemented on the thin trait object. Calls the contained closure.
+//! - **A pinned thin trait object struct definition**, only if the trait has methods which take `self: Pin<&Self>` or `self: Pin<&mut Self>`.
+//!
+//!   The name is `Pinned{trait name}`, as in, `PinnedFoo` for a trait named `Foo`, and the visibility is the same as that of the thin trait object struct, which it wraps. It implements [`Unpin`] and `From<BoxedFoo>`, and has a `new` constructor like that of the thin trait object. Every method of the trait which takes `&self`, `self: Pin<&Self>` or `self: Pin<&mut Self>` is also present as an inherent method, with the latter two taking `&self` and `&mut self` respectively.
+//! - **A stack object struct definition and a borrowed thin trait object struct definition**, only if the `stack_objects = true` option is used.
+//!
+//!   The names are `Stack{trait name}` and `Mut{trait name}`, as in, `StackFoo` and `MutFoo` for a trait named `Foo`, and the visibility is the same as that of the thin trait object struct. `StackFoo<T>` has the `new` constructor, as well as `new_with_header` if the `header(...)` option is used, in which case `new` requires the header type to implement `Default`, `as_thin(&mut self) -> MutFoo<'_>` and `into_inner(self) -> T`. `MutFoo<'a>` is `#[repr(transparent)]` over a pointer, has the `from_raw`, `as_raw` and `vtable` methods, implements the trait, the forwarded supertraits and the marker traits the same way the thin trait object does, and does nothing when dropped. If the trait has a `'static` bound, which a borrowed object cannot satisfy, the methods of the trait are inherent methods of `MutFoo` instead.
+//! - **An inline object struct definition**, only if the `inline_storage = true` option is used.
+//!
+//!   The name is `Inline{trait name}`, as in, `InlineFoo` for a trait named `Foo`, and the visibility is the same as that of the thin trait object struct. It has a `const N: usize` parameter after the lifetime, if any, which is the number of bytes available for the value. A value is stored inline if it fits and is aligned no more strictly than the vtable pointer, or boxed as a thin trait object otherwise. `InlineFoo` has the `new` constructor, as well as `new_with_header` if the `header(...)` option is used, in which case `new` requires the header type to implement `Default`, and the `is_inline`, `as_raw` and `vtable` methods. It implements the trait, the forwarded supertraits and the marker traits the same way the thin trait object does. Unlike with the thin trait object, the pointer returned by `as_raw` only stays valid until the inline object is moved.
+//! - **Implementations of the forwarded standard library supertraits on the thin trait object**, if there are any, as described in the [Supertraits](#forwarded-standard-library-traits) section.
+//! - **An implementation of the trait for all closures which implement its supertraits**, only if it has an `Fn`, `FnMut` or `FnOnce` supertrait.
+//! - **An implementation of the trait for `Box<dyn {trait name} + 'inner>`**, only if the `dyn_conversions = true` option is used without `blanket_impl = true`.
+//! - **A buffer struct definition**, only if the `marshal` option is used.
+//!
+//!   The name is `{trait name}FfiBuffer`, as in, `FooFfiBuffer` for a trait named `Foo`, and the visibility is taken from the trait definition. It is defined as follows:
+//!   ```no_run
+//!   # /*
+//!   #[repr(C)]
+//!   struct FooFfiBuffer<T> {
+//!       ptr: *mut T,
+//!       len: usize,
+//!       capacity: usize,
+//!       free: unsafe extern "C" fn(*mut T, usize, usize),
+//!   }
+//!   # */
+//!   ```
+//!   All fields are public. The `from_vec` and `into_vec` methods convert between the buffer and a `Vec`, and the `Default` implementation creates an empty buffer.
 //!
 //! [*FFI-Safe Polymorphism: Thin Trait Objects*]: https://adventures.michaelfbryan.com/posts/ffi-safe-polymorphism-in-rust/ " "
 //! [virtual dispatch table]: https://en.wikipedia.org/wiki/Virtual_method_table " "
 //! [`core::ptr::NonNull`]: https://doc.rust-lang.org/std/ptr/struct.NonNull.html " "
 //! [`core::ffi::c_void`]: https://doc.rust-lang.org/std/ffi/enum.c_void.html " "
 //! [`ManuallyDrop`]: https://doc.rust-lang.org/std/mem/struct.ManuallyDrop.html " "
+//! [`MaybeUninit`]: https://doc.rust-lang.org/std/mem/union.MaybeUninit.html " "
 //! [`mem::forget`]: https://doc.rust-lang.org/std/mem/fn.forget.html " "
+//! [`GlobalAlloc`]: https://doc.rust-lang.org/std/alloc/trait.GlobalAlloc.html " "
+//! [`Unpin`]: https://doc.rust-lang.org/std/marker/trait.Unpin.html " "
+//! [`PhantomPinned`]: https://doc.rust-lang.org/std/marker/struct.PhantomPinned.html " "
+//! [`thin_trait_object_runtime`]: https://docs.rs/thin_trait_object_runtime " "
 
 #![deny(rust_2018_idioms)]
 #![warn(missing_docs, clippy::cargo)]
@@ -370,6 +1130,19 @@ pub fn thin_trait_object(attr: TokenStream, mut item: TokenStream) -> TokenStrea
     item
 }
 
+/// Creates a trait and a thin trait object interface for it from an existing vtable struct.
+///
+/// See the [Implementing existing vtables](index.html#implementing-existing-vtables) section of the crate-level documentation for more.
+#[proc_macro_attribute]
+pub fn thin_trait_object_from_vtable(attr: TokenStream, mut item: TokenStream) -> TokenStream {
+    let output: TokenStream = from_vtable_main(attr.into(), item.clone().into())
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into();
+    // Same deal as with the main attribute, see above.
+    item.extend(Some(output));
+    item
+}
+
 #[macro_use]
 pub(crate) mod util {
     macro_rules! define_path {
@@ -424,10 +1197,18 @@ pub(crate) mod util {
 
 mod attr;
 use attr::*;
+mod from_vtable;
+use from_vtable::*;
+pub(crate) mod closure;
+pub(crate) mod dyn_conversions;
+pub(crate) mod forward;
+pub(crate) mod lowering;
 pub(crate) mod marker_traits;
+pub(crate) mod marshal;
 pub(crate) mod options;
 pub(crate) mod repr;
 pub(crate) mod trait_object;
+pub(crate) mod unwind;
 pub(crate) mod vtable;
 
 /// Convinces [`cargo geiger`] that the crate has unsafe code.
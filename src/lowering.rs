@@ -0,0 +1,157 @@
+//! Lowering of `Result` return types into error codes, which can cross the FFI boundary, and lifting them back.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::{Abi, BareFnArg, GenericArgument, LitStr, PathArguments, ReturnType, Type, TypePtr};
+
+use crate::{marshal::Marshal, vtable::VtableItem};
+
+/// The success and error types of a method whose `Result` return type is lowered to an error code.
+#[derive(Clone)]
+pub struct LoweredResult {
+    pub ok: Type,
+    pub err: Type,
+    /// How the success value is marshalled, if at all.
+    pub ok_marshal: Option<Marshal>,
+}
+impl LoweredResult {
+    /// Recognizes `Result<T, E>` in the return type. Aliases with a single generic argument, like `io::Result<T>`, are not recognized, since the error type cannot be determined from them.
+    pub fn from_output(output: &ReturnType) -> Option<Self> {
+        let ty = match output {
+            ReturnType::Type(_, ty) => ty,
+            ReturnType::Default => return None,
+        };
+        let path = match &**ty {
+            Type::Path(path) if path.qself.is_none() => &path.path,
+            _ => return None,
+        };
+        let segment = path.segments.last().filter(|x| x.ident == "Result")?;
+        let args = match &segment.arguments {
+            PathArguments::AngleBracketed(args) if args.args.len() == 2 => &args.args,
+            _ => return None,
+        };
+        match (&args[0], &args[1]) {
+            (GenericArgument::Type(ok), GenericArgument::Type(err)) => Some(Self {
+                ok: ok.clone(),
+                err: err.clone(),
+                ok_marshal: None,
+            }),
+            _ => None,
+        }
+    }
+    /// Whether the success type is `()`, in which case the out-pointer is omitted.
+    pub fn ok_is_unit(&self) -> bool {
+        matches!(&self.ok, Type::Tuple(tuple) if tuple.elems.is_empty())
+    }
+    /// The out-pointer argument which is appended to the vtable entry, if needed.
+    pub fn out_arg(&self) -> Option<BareFnArg> {
+        if self.ok_is_unit() {
+            return None;
+        }
+        Some(BareFnArg {
+            attrs: Vec::new(),
+            // The thunks name it along with the other unnamed arguments, which avoids collisions.
+            name: None,
+            ty: Type::Ptr(TypePtr {
+                star_token: Default::default(),
+                const_token: None,
+                mutability: Some(Default::default()),
+                elem: Box::new(self.raw_ok()),
+            }),
+        })
+    }
+    /// The type of the success value as written to the out-pointer.
+    fn raw_ok(&self) -> Type {
+        match &self.ok_marshal {
+            Some(marshal) => marshal.raw_owned_type(),
+            None => self.ok.clone(),
+        }
+    }
+    /// The return type of the vtable entry.
+    pub fn raw_output() -> ReturnType {
+        ReturnType::Type(Default::default(), Box::new(syn::parse_quote!(i32)))
+    }
+    /// Converts the `Result` returned by `call` into an error code inside a thunk, writing the success value into `out`.
+    pub fn lower(&self, call: TokenStream, out: Option<&Ident>) -> TokenStream {
+        let err = &self.err;
+        let ok = match &self.ok_marshal {
+            Some(marshal) => marshal.lower_owned(quote! { __thintraitobjectmacro_ok }),
+            None => quote! { __thintraitobjectmacro_ok },
+        };
+        let write_ok = match out {
+            Some(out) => quote! { ::core::ptr::write(#out, #ok); },
+            None => quote! { let _ = __thintraitobjectmacro_ok; },
+        };
+        quote! {
+            match #call {
+                ::core::result::Result::Ok(__thintraitobjectmacro_ok) => {
+                    #write_ok
+                    0
+                }
+                ::core::result::Result::Err(__thintraitobjectmacro_err) => {
+                    <#err as ::core::convert::Into<::core::num::NonZeroI32>>::into(
+                        __thintraitobjectmacro_err,
+                    )
+                    .get()
+                }
+            }
+        }
+    }
+    /// Calls the vtable entry with the specified function and arguments and converts the error code back into a `Result`. `after_call` is inserted between the call and the conversion.
+    pub fn lift(
+        &self,
+        function: TokenStream,
+        args: TokenStream,
+        after_call: TokenStream,
+    ) -> TokenStream {
+        let err = &self.err;
+        let (out_decl, out_arg, ok_val) = if self.ok_is_unit() {
+            (quote! {}, quote! {}, quote! { () })
+        } else {
+            let raw_ok = self.raw_ok();
+            let out_val = quote! { __thintraitobjectmacro_out.assume_init() };
+            (
+                quote! {
+                    let mut __thintraitobjectmacro_out =
+                        ::core::mem::MaybeUninit::<#raw_ok>::uninit();
+                },
+                quote! { , __thintraitobjectmacro_out.as_mut_ptr() },
+                match &self.ok_marshal {
+                    Some(marshal) => marshal.lift_owned(out_val),
+                    None => out_val,
+                },
+            )
+        };
+        quote! {
+            #out_decl
+            let __thintraitobjectmacro_code = (#function)(#args #out_arg);
+            #after_call
+            match ::core::num::NonZeroI32::new(__thintraitobjectmacro_code) {
+                ::core::option::Option::None => ::core::result::Result::Ok(#ok_val),
+                ::core::option::Option::Some(code) => {
+                    ::core::result::Result::Err(
+                        <#err as ::core::convert::From<::core::num::NonZeroI32>>::from(code),
+                    )
+                }
+            }
+        }
+    }
+    /// The error code returned by a thunk when the panic policy discards a panic.
+    pub fn panic_return() -> TokenStream {
+        quote! { ::core::primitive::i32::MIN }
+    }
+}
+
+/// Marks the methods returning a `Result` as lowered, giving them the `"C"` ABI in the vtable unless they have a foreign ABI already.
+pub fn lower_results(items: &mut [VtableItem]) {
+    // The signatures of forwarded supertrait methods are fixed.
+    for item in items.iter_mut().filter(|item| item.forwarded.is_none()) {
+        item.lowered_result = LoweredResult::from_output(&item.output);
+        if item.lowered_result.is_some() && !item.has_foreign_abi() {
+            item.slot_abi = Some(Abi {
+                extern_token: Default::default(),
+                name: Some(LitStr::new("C", Span::call_site())),
+            });
+        }
+    }
+}
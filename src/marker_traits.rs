@@ -1,9 +1,8 @@
 //! Handling of marker supertraits of traits annotated with `#[thin_trait_object]`.
 
-use once_cell::unsync::Lazy;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
-use std::{borrow::Borrow, iter};
+use std::iter;
 use syn::{
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
@@ -21,11 +20,15 @@ pub struct MarkerTrait {
     pub path: Path,
 }
 impl MarkerTrait {
-    pub fn as_impl_for(&self, implementor: &impl ToTokens) -> TokenStream {
+    pub fn as_impl_for(
+        &self,
+        generics: &impl ToTokens,
+        implementor: &impl ToTokens,
+    ) -> TokenStream {
         let marker_unsafety = self.unsafety.as_ref();
         let marker_path = &self.path;
         quote! {
-            #marker_unsafety impl #marker_path for #implementor {}
+            #marker_unsafety impl #generics #marker_path for #implementor {}
         }
     }
 }
@@ -95,39 +98,36 @@ fn mkseg(string: &str) -> PathSegment {
 }
 
 pub fn default_marker_filter(bound: TraitBound) -> Option<(TraitBound, bool)> {
-    LOOKUP_TABLE.with(|lookup_table| {
-        for (short_name, full_path, is_unsafe) in lookup_table.borrow().iter().cloned() {
-            if bound.path == make_path!(mkseg(short_name)) || bound.path == full_path {
-                return Some((bound, is_unsafe));
-            }
+    for (short_name, full_path, is_unsafe) in lookup_table().iter().cloned() {
+        if bound.path == make_path!(mkseg(short_name)) || bound.path == full_path {
+            return Some((bound, is_unsafe));
         }
-        None
-    })
+    }
+    None
 }
 
-thread_local! {
-    static LOOKUP_TABLE: Lazy<[(&'static str, Path, bool); 4]> = Lazy::new(|| {
-        [
-            (
-                "Send",
-                make_path![mkseg("core"), mkseg("marker"), mkseg("Send")],
-                true,
-            ),
-            (
-                "Sync",
-                make_path![mkseg("core"), mkseg("marker"), mkseg("Sync")],
-                true,
-            ),
-            (
-                "UnwindSafe",
-                make_path![mkseg("std"), mkseg("panic"), mkseg("UnwindSafe")],
-                false,
-            ),
-            (
-                "RefUnwindSafe",
-                make_path![mkseg("std"), mkseg("panic"), mkseg("RefUnwindSafe")],
-                false,
-            ),
-        ]
-    });
+/// Built anew for every invocation of the macro, since identifiers cannot outlive it.
+fn lookup_table() -> [(&'static str, Path, bool); 4] {
+    [
+        (
+            "Send",
+            make_path![mkseg("core"), mkseg("marker"), mkseg("Send")],
+            true,
+        ),
+        (
+            "Sync",
+            make_path![mkseg("core"), mkseg("marker"), mkseg("Sync")],
+            true,
+        ),
+        (
+            "UnwindSafe",
+            make_path![mkseg("std"), mkseg("panic"), mkseg("UnwindSafe")],
+            false,
+        ),
+        (
+            "RefUnwindSafe",
+            make_path![mkseg("std"), mkseg("panic"), mkseg("RefUnwindSafe")],
+            false,
+        ),
+    ]
 }
@@ -0,0 +1,503 @@
+//! Marshalling of common Rust types which have no stable layout and of other thin trait objects into FFI-safe equivalents in the vtable signatures.
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    token::Mut,
+    Attribute,
+    BareFnArg,
+    GenericArgument,
+    Path,
+    PathArguments,
+    ReturnType,
+    Token,
+    Type,
+    TypeParamBound,
+    Visibility,
+};
+
+use crate::vtable::{VtableFnArg, VtableItem};
+
+/// A type in a method signature, along with how it's represented in the vtable.
+#[derive(Clone)]
+pub enum Marshal {
+    /// `&str`, passed as a pointer to the bytes and the length.
+    Str,
+    /// `&[T]` or `&mut [T]`, passed as a pointer to the elements and the length.
+    Slice { elem: Type, mutability: Option<Mut> },
+    /// `Option<&T>` or `Option<&mut T>`, passed as a nullable pointer.
+    OptionRef { elem: Type, mutability: Option<Mut> },
+    /// `String`, passed as a buffer of bytes.
+    String { buffer: Ident },
+    /// `Vec<T>`, passed as a buffer of elements.
+    Vec { elem: Type, buffer: Ident },
+    /// The thin trait object of another trait, passed as the pointer returned by `into_raw`. Ownership is transferred to the receiving side.
+    ThinObject { trait_object: Path },
+    /// A reference to the thin trait object of another trait, passed as the pointer returned by `as_raw`. The object stays owned by the caller.
+    ThinObjectRef { trait_object: Path },
+    /// `impl Trait` for another thin trait, boxed into its thin trait object and passed like one.
+    ImplThinTrait { trait_object: Path },
+}
+impl Marshal {
+    /// Recognizes a type which is marshalled in argument position. Strings, slices and vectors are only marshalled if a buffer is provided.
+    pub fn from_arg_type(
+        ty: &Type,
+        buffer: Option<&Ident>,
+        thin_traits: &[ThinTrait],
+    ) -> Option<Self> {
+        if let Type::Reference(reference) = ty {
+            if reference.mutability.is_none() {
+                if let Some(thin_trait) =
+                    ThinTrait::find_by_trait_object(thin_traits, &reference.elem)
+                {
+                    return Some(Self::ThinObjectRef {
+                        trait_object: thin_trait.trait_object.clone(),
+                    });
+                }
+            }
+            buffer?;
+            return match &*reference.elem {
+                Type::Path(path) if reference.mutability.is_none() && path.path.is_ident("str") => {
+                    Some(Self::Str)
+                }
+                Type::Slice(slice) => Some(Self::Slice {
+                    elem: (*slice.elem).clone(),
+                    mutability: reference.mutability,
+                }),
+                _ => None,
+            };
+        }
+        match single_generic_argument(ty, "Option") {
+            Some(..) if buffer.is_none() => return None,
+            // Pointers to unsized types are fat and thus not FFI-safe.
+            Some(Type::Reference(reference))
+                if !matches!(&*reference.elem, Type::Slice(..) | Type::TraitObject(..))
+                    && !matches!(&*reference.elem, Type::Path(path) if path.path.is_ident("str")) =>
+            {
+                return Some(Self::OptionRef {
+                    elem: (*reference.elem).clone(),
+                    mutability: reference.mutability,
+                })
+            }
+            Some(..) => return None,
+            None => {}
+        }
+        Self::from_owned_type(ty, buffer, thin_traits)
+    }
+    /// Recognizes a type which is marshalled in return position. Only owned types are, since borrowed ones would need a lifetime which the vtable entry does not have.
+    pub fn from_owned_type(
+        ty: &Type,
+        buffer: Option<&Ident>,
+        thin_traits: &[ThinTrait],
+    ) -> Option<Self> {
+        if let Some(thin_trait) = ThinTrait::find_by_trait_object(thin_traits, ty) {
+            return Some(Self::ThinObject {
+                trait_object: thin_trait.trait_object.clone(),
+            });
+        }
+        if let Some(thin_trait) = ThinTrait::find_by_impl_trait(thin_traits, ty) {
+            return Some(Self::ImplThinTrait {
+                trait_object: thin_trait.trait_object.clone(),
+            });
+        }
+        let buffer = buffer?;
+        if let Some(elem) = single_generic_argument(ty, "Vec") {
+            return Some(Self::Vec {
+                elem: elem.clone(),
+                buffer: buffer.clone(),
+            });
+        }
+        match ty {
+            Type::Path(path) if path.qself.is_none() => {
+                let segment = path.path.segments.last()?;
+                if segment.ident == "String" && segment.arguments.is_empty() {
+                    Some(Self::String {
+                        buffer: buffer.clone(),
+                    })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+    /// The arguments which the type is passed as in the vtable, named after the original argument if it had a name.
+    pub fn raw_args(&self, attrs: &[Attribute], name: Option<&Ident>) -> Vec<BareFnArg> {
+        let raw_arg = |suffix: Option<&str>, ty: Type| BareFnArg {
+            attrs: attrs.to_vec(),
+            name: name.map(|name| {
+                let name = match suffix {
+                    Some(suffix) => format_ident!("{}_{}", name, suffix),
+                    None => name.clone(),
+                };
+                (name, Default::default())
+            }),
+            ty,
+        };
+        match self {
+            Self::Str => vec![
+                raw_arg(Some("ptr"), syn::parse_quote!(*const u8)),
+                raw_arg(Some("len"), syn::parse_quote!(usize)),
+            ],
+            Self::Slice { elem, mutability } => {
+                let pointer = if mutability.is_some() {
+                    syn::parse_quote!(*mut #elem)
+                } else {
+                    syn::parse_quote!(*const #elem)
+                };
+                vec![
+                    raw_arg(Some("ptr"), pointer),
+                    raw_arg(Some("len"), syn::parse_quote!(usize)),
+                ]
+            }
+            Self::OptionRef { elem, mutability } => {
+                let pointer = if mutability.is_some() {
+                    syn::parse_quote!(*mut #elem)
+                } else {
+                    syn::parse_quote!(*const #elem)
+                };
+                vec![raw_arg(None, pointer)]
+            }
+            Self::String { .. }
+            | Self::Vec { .. }
+            | Self::ThinObject { .. }
+            | Self::ImplThinTrait { .. } => vec![raw_arg(None, self.raw_owned_type())],
+            Self::ThinObjectRef { .. } => {
+                vec![raw_arg(None, syn::parse_quote!(*mut ::core::ffi::c_void))]
+            }
+        }
+    }
+    /// The number of arguments which the type is passed as in the vtable.
+    pub fn raw_arg_count(&self) -> usize {
+        match self {
+            Self::Str | Self::Slice { .. } => 2,
+            _ => 1,
+        }
+    }
+    /// The type which an owned type is passed as in the vtable.
+    pub fn raw_owned_type(&self) -> Type {
+        match self {
+            Self::String { buffer } => syn::parse_quote!(#buffer<u8>),
+            Self::Vec { elem, buffer } => syn::parse_quote!(#buffer<#elem>),
+            Self::ThinObject { .. } | Self::ImplThinTrait { .. } => {
+                syn::parse_quote!(*mut ::core::ffi::c_void)
+            }
+            _ => unreachable!("only owned types are passed as a single value"),
+        }
+    }
+    /// Converts the raw arguments received by a thunk into the value expected by the method.
+    pub fn lift_arg(&self, raw: &[Ident]) -> TokenStream {
+        match self {
+            Self::Str => {
+                let (ptr, len) = (&raw[0], &raw[1]);
+                // The bytes come from C code, which doesn't necessarily uphold the invariants of `str`.
+                // Panicking would unwind into C code, and replacing the invalid bytes would need an
+                // allocation which the borrowed string cannot outlive, so only the valid part is
+                // passed on.
+                quote! {
+                    {
+                        let __thintraitobjectmacro_bytes: &[u8] = if #len == 0 {
+                            &[]
+                        } else {
+                            ::core::slice::from_raw_parts(#ptr, #len)
+                        };
+                        match ::core::str::from_utf8(__thintraitobjectmacro_bytes) {
+                            ::core::result::Result::Ok(__thintraitobjectmacro_str) => {
+                                __thintraitobjectmacro_str
+                            }
+                            ::core::result::Result::Err(__thintraitobjectmacro_error) => {
+                                ::core::str::from_utf8_unchecked(
+                                    &__thintraitobjectmacro_bytes
+                                        [..__thintraitobjectmacro_error.valid_up_to()],
+                                )
+                            }
+                        }
+                    }
+                }
+            }
+            Self::Slice { mutability, .. } => {
+                let (ptr, len) = (&raw[0], &raw[1]);
+                if mutability.is_some() {
+                    quote! {
+                        if #len == 0 {
+                            &mut []
+                        } else {
+                            ::core::slice::from_raw_parts_mut(#ptr, #len)
+                        }
+                    }
+                } else {
+                    quote! {
+                        if #len == 0 {
+                            &[]
+                        } else {
+                            ::core::slice::from_raw_parts(#ptr, #len)
+                        }
+                    }
+                }
+            }
+            Self::OptionRef { mutability, .. } => {
+                let ptr = &raw[0];
+                if mutability.is_some() {
+                    quote! { #ptr.as_mut() }
+                } else {
+                    quote! { #ptr.as_ref() }
+                }
+            }
+            Self::ThinObjectRef { trait_object } => {
+                let ptr = &raw[0];
+                // The object is not owned by the thunk, so it must not be dropped.
+                quote! {
+                    &*::core::mem::ManuallyDrop::new(#trait_object::from_raw(#ptr as *mut ()))
+                }
+            }
+            Self::String { .. }
+            | Self::Vec { .. }
+            | Self::ThinObject { .. }
+            | Self::ImplThinTrait { .. } => self.lift_owned(raw[0].clone()),
+        }
+    }
+    /// Converts an argument of the method into the raw arguments passed to the vtable entry, separated by commas.
+    pub fn lower_arg(&self, value: TokenStream) -> TokenStream {
+        match self {
+            Self::Str
+            | Self::Slice {
+                mutability: None, ..
+            } => quote! {
+                #value.as_ptr(), #value.len()
+            },
+            Self::Slice { .. } => quote! {
+                #value.as_mut_ptr(), #value.len()
+            },
+            Self::OptionRef {
+                mutability: None, ..
+            } => quote! {
+                #value.map_or(::core::ptr::null(), |x| x as *const _)
+            },
+            Self::OptionRef { .. } => quote! {
+                #value.map_or(::core::ptr::null_mut(), |x| x as *mut _)
+            },
+            Self::ThinObjectRef { trait_object } => quote! {
+                #trait_object::as_raw(#value) as *mut ::core::ffi::c_void
+            },
+            Self::String { .. }
+            | Self::Vec { .. }
+            | Self::ThinObject { .. }
+            | Self::ImplThinTrait { .. } => self.lower_owned(value),
+        }
+    }
+    /// Converts a raw owned value back into the Rust type, releasing the buffer.
+    pub fn lift_owned(&self, value: impl ToTokens) -> TokenStream {
+        let alloc_crate = alloc_crate_name();
+        match self {
+            // Invalid UTF-8 coming from C code is replaced rather than trusted.
+            Self::String { buffer } => quote! {
+                ::#alloc_crate::string::String::from_utf8(#buffer::into_vec(#value))
+                    .unwrap_or_else(|error| {
+                        ::#alloc_crate::string::String::from_utf8_lossy(error.as_bytes()).into_owned()
+                    })
+            },
+            Self::Vec { buffer, .. } => quote! { #buffer::into_vec(#value) },
+            Self::ThinObject { trait_object } | Self::ImplThinTrait { trait_object } => quote! {
+                #trait_object::from_raw(#value as *mut ())
+            },
+            _ => unreachable!("only owned types are passed as a single value"),
+        }
+    }
+    /// Converts an owned value into its raw form.
+    pub fn lower_owned(&self, value: impl ToTokens) -> TokenStream {
+        let alloc_crate = alloc_crate_name();
+        match self {
+            Self::String { buffer } => quote! {
+                #buffer::from_vec(::#alloc_crate::string::String::into_bytes(#value))
+            },
+            Self::Vec { buffer, .. } => quote! { #buffer::from_vec(#value) },
+            Self::ThinObject { trait_object } => quote! {
+                #trait_object::into_raw(#value) as *mut ::core::ffi::c_void
+            },
+            Self::ImplThinTrait { trait_object } => quote! {
+                #trait_object::into_raw(#trait_object::new(#value)) as *mut ::core::ffi::c_void
+            },
+            _ => unreachable!("only owned types are passed as a single value"),
+        }
+    }
+}
+
+/// Another trait annotated with `#[thin_trait_object]`, whose thin trait object can be passed to and returned from methods. Specified with the `thin_traits` option.
+#[derive(Clone)]
+pub struct ThinTrait {
+    pub path: Path,
+    /// The thin trait object struct, `Boxed{trait name}` in the same module as the trait unless specified otherwise.
+    pub trait_object: Path,
+}
+impl ThinTrait {
+    /// Finds the trait whose thin trait object the type is, disregarding the generic arguments.
+    fn find_by_trait_object<'a>(thin_traits: &'a [ThinTrait], ty: &Type) -> Option<&'a Self> {
+        let path = match ty {
+            Type::Path(path) if path.qself.is_none() => &path.path,
+            _ => return None,
+        };
+        thin_traits.iter().find(|thin_trait| {
+            let trait_object = &thin_trait.trait_object;
+            trait_object.leading_colon.is_some() == path.leading_colon.is_some()
+                && trait_object.segments.len() == path.segments.len()
+                && trait_object
+                    .segments
+                    .iter()
+                    .zip(&path.segments)
+                    .all(|(a, b)| a.ident == b.ident)
+        })
+    }
+    /// Finds the trait which the type is `impl Trait` of.
+    fn find_by_impl_trait<'a>(thin_traits: &'a [ThinTrait], ty: &Type) -> Option<&'a Self> {
+        let bounds = match ty {
+            Type::ImplTrait(impl_trait) => &impl_trait.bounds,
+            _ => return None,
+        };
+        bounds.iter().find_map(|bound| match bound {
+            TypeParamBound::Trait(bound) => thin_traits
+                .iter()
+                .find(|thin_trait| thin_trait.path == bound.path),
+            _ => None,
+        })
+    }
+}
+impl Parse for ThinTrait {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let path = input.parse::<Path>()?;
+        let trait_object = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            input.parse()?
+        } else {
+            let mut trait_object = path.clone();
+            let last = trait_object
+                .segments
+                .last_mut()
+                .expect("paths cannot be empty");
+            last.ident = format_ident!("Boxed{}", last.ident);
+            last.arguments = PathArguments::None;
+            trait_object
+        };
+        Ok(Self { path, trait_object })
+    }
+}
+
+/// Returns the type argument of `Name<T>`, as in `Option<T>` or `Vec<T>`.
+fn single_generic_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last().filter(|x| x.ident == name)?;
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[inline]
+pub fn buffer_name_from_trait_name(trait_name: &Ident) -> Ident {
+    format_ident!("{}FfiBuffer", trait_name)
+}
+
+/// Marks the arguments and return types of methods for marshalling. Thin trait objects are marshalled in all vtable entries, while buffers are only used in those with a foreign ABI.
+pub fn marshal_items(items: &mut [VtableItem], buffer: Option<&Ident>, thin_traits: &[ThinTrait]) {
+    // The signatures of forwarded supertrait methods are fixed.
+    for item in items.iter_mut().filter(|item| item.forwarded.is_none()) {
+        let buffer = buffer.filter(|_| item.has_foreign_abi());
+        item.marshalled_args = item
+            .inputs
+            .iter()
+            .map(|input| match input {
+                VtableFnArg::Normal(arg) => Marshal::from_arg_type(&arg.ty, buffer, thin_traits),
+                VtableFnArg::Receiver(..) | VtableFnArg::PinnedReceiver(..) => None,
+            })
+            .collect();
+        match &mut item.lowered_result {
+            Some(lowered_result) => {
+                lowered_result.ok_marshal =
+                    Marshal::from_owned_type(&lowered_result.ok, buffer, thin_traits);
+            }
+            None => {
+                if let ReturnType::Type(_, ty) = &item.output {
+                    item.marshalled_output = Marshal::from_owned_type(ty, buffer, thin_traits);
+                }
+            }
+        }
+    }
+}
+
+/// Generates the buffer struct through which owned types are passed.
+pub fn generate_buffer(name: &Ident, visibility: &Visibility) -> TokenStream {
+    let alloc_crate = alloc_crate_name();
+    quote! {
+        /// An owned buffer, through which `String` and `Vec` values cross the FFI boundary.
+        ///
+        /// Whoever receives the buffer becomes responsible for calling `free` on it exactly once.
+        #[repr(C)]
+        #visibility struct #name<T> {
+            /// Pointer to the first element.
+            pub ptr: *mut T,
+            /// The number of elements.
+            pub len: usize,
+            /// The number of elements which the allocation can hold.
+            pub capacity: usize,
+            /// Drops the first `len` elements and releases the allocation. Called with the pointer, length and capacity of the buffer.
+            pub free: unsafe extern "C" fn(*mut T, usize, usize),
+        }
+        impl<T> #name<T> {
+            /// Creates a buffer which releases the memory using the global allocator.
+            #visibility fn from_vec(vec: ::#alloc_crate::vec::Vec<T>) -> Self {
+                let mut vec = ::core::mem::ManuallyDrop::new(vec);
+                Self {
+                    ptr: vec.as_mut_ptr(),
+                    len: vec.len(),
+                    capacity: vec.capacity(),
+                    free: Self::free_vec,
+                }
+            }
+            /// Moves the elements into a `Vec` and releases the buffer.
+            ///
+            /// # Safety
+            /// The buffer must be valid, i.e. `ptr` must point to `len` initialized elements, and calling `free` with it must be sound.
+            #visibility unsafe fn into_vec(self) -> ::#alloc_crate::vec::Vec<T> {
+                // A buffer created by `from_vec` already holds the allocation of a `Vec`. Function
+                // pointers aren't guaranteed to be unique, which can only cause a needless copy.
+                let free_vec = Self::free_vec as unsafe extern "C" fn(*mut T, usize, usize);
+                if self.free as usize == free_vec as usize {
+                    return ::#alloc_crate::vec::Vec::from_raw_parts(self.ptr, self.len, self.capacity);
+                }
+                let mut vec = ::#alloc_crate::vec::Vec::with_capacity(self.len);
+                if self.len != 0 {
+                    ::core::ptr::copy_nonoverlapping(self.ptr, vec.as_mut_ptr(), self.len);
+                    vec.set_len(self.len);
+                }
+                // The elements were moved out, so only the memory is released.
+                (self.free)(self.ptr, 0, self.capacity);
+                vec
+            }
+            unsafe extern "C" fn free_vec(ptr: *mut T, len: usize, capacity: usize) {
+                ::core::mem::drop(::#alloc_crate::vec::Vec::from_raw_parts(ptr, len, capacity));
+            }
+        }
+        impl<T> ::core::default::Default for #name<T> {
+            /// Creates an empty buffer.
+            fn default() -> Self {
+                Self::from_vec(::#alloc_crate::vec::Vec::new())
+            }
+        }
+    }
+}
+
+/// The name of the crate which provides `Vec` and `String`: `std` with the `std` feature, `alloc` otherwise.
+pub fn alloc_crate_name() -> Ident {
+    let name = if cfg!(feature = "std") {
+        "std"
+    } else {
+        "alloc"
+    };
+    Ident::new(name, Span::call_site())
+}
@@ -1,6 +1,6 @@
 //! Everything related to parsing the options of the attribute macro.
 
-use proc_macro2::Ident;
+use proc_macro2::{Ident, Span};
 use std::borrow::Borrow;
 use syn::{
     parenthesized,
@@ -10,14 +10,18 @@ use syn::{
     Attribute,
     LitBool,
     LitStr,
+    Path,
     Token,
+    Type,
     Visibility,
 };
 
-use crate::marker_traits::MarkerTrait;
+use crate::{marker_traits::MarkerTrait, marshal::ThinTrait, unwind::PanicPolicy};
 
 pub type AttrOptions = Punctuated<AttrOption, Token![,]>;
 
+// Tokens which are never read, such as the punctuation, are kept around for the sake of having a
+// complete syntax tree.
 pub enum AttrOption {
     /// Overrides the visibility modifier, name and optionally adds attributes to the generated vtable struct.
     ///
@@ -35,6 +39,7 @@ pub enum AttrOption {
     /// ```
     Vtable {
         name: custom_token::Vtable,
+        #[allow(dead_code)]
         paren: token::Paren,
         additions: OutputAdditions,
     },
@@ -50,6 +55,7 @@ pub enum AttrOption {
     /// ```
     InlineVtable {
         name: custom_token::InlineVtable,
+        #[allow(dead_code)]
         eq: Token![=],
         val: LitBool,
     },
@@ -69,6 +75,7 @@ pub enum AttrOption {
     /// ```
     TraitObject {
         name: custom_token::TraitObject,
+        #[allow(dead_code)]
         paren: token::Paren,
         additions: OutputAdditions,
     },
@@ -84,6 +91,7 @@ pub enum AttrOption {
     /// ```
     DropAbi {
         name: custom_token::DropAbi,
+        #[allow(dead_code)]
         eq: Token![=],
         abi: LitStr,
     },
@@ -105,6 +113,7 @@ pub enum AttrOption {
     /// ```
     MarkerTraits {
         name: custom_token::MarkerTraits,
+        #[allow(dead_code)]
         paren: token::Paren,
         marker_traits: Punctuated<MarkerTrait, Token![,]>,
     },
@@ -120,15 +129,292 @@ pub enum AttrOption {
     /// ```
     StoreLayout {
         name: custom_token::StoreLayout,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Specifies a type which is to be placed at the start of the object, before the vtable pointer.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     header(FileHeader),
+    /// )]
+    /// # */
+    /// ```
+    Header {
+        name: custom_token::Header,
+        #[allow(dead_code)]
+        paren: token::Paren,
+        ty: Type,
+    },
+    /// Overrides the visibility modifier, name and optionally adds attributes to the trait generated by `#[thin_trait_object_from_vtable]`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object_from_vtable(
+    ///     trait_def(
+    ///         /// Documentation for my trait!
+    ///         pub MyTrait
+    ///     ),
+    /// )]
+    /// # */
+    /// ```
+    TraitDef {
+        name: custom_token::TraitDef,
+        #[allow(dead_code)]
+        paren: token::Paren,
+        additions: OutputAdditions,
+    },
+    /// Specifies which field of the vtable struct given to `#[thin_trait_object_from_vtable]` is the destructor.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object_from_vtable(
+    ///     drop_slot = release,
+    /// )]
+    /// # */
+    /// ```
+    DropSlot {
+        name: custom_token::DropSlot,
+        #[allow(dead_code)]
+        eq: Token![=],
+        slot: Ident,
+    },
+    /// Specifies what happens when a method with a foreign ABI panics when called through the vtable.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     on_panic = "catch_and_store",
+    /// )]
+    /// # */
+    /// ```
+    OnPanic {
+        name: custom_token::OnPanic,
+        #[allow(dead_code)]
+        eq: Token![=],
+        #[allow(dead_code)]
+        lit: LitStr,
+        policy: PanicPolicy,
+    },
+    /// Sets whether methods returning `Result<T, E>` will be lowered to error codes in the vtable.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     lower_results = true,
+    /// )]
+    /// # */
+    /// ```
+    LowerResults {
+        name: custom_token::LowerResults,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Sets whether the types in vtable entries with a foreign ABI will be checked for FFI-safety.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     strict_ffi = true,
+    /// )]
+    /// # */
+    /// ```
+    StrictFfi {
+        name: custom_token::StrictFfi,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Specifies the ABI of the vtable entries for methods which don't have one specified in the trait definition, as well as the default for `drop_abi`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     abi = "C",
+    /// )]
+    /// # */
+    /// ```
+    Abi {
+        name: custom_token::Abi,
+        #[allow(dead_code)]
+        eq: Token![=],
+        abi: LitStr,
+    },
+    /// Sets whether strings, slices, `Option`s of references and vectors will be marshalled into FFI-safe types in vtable entries with a foreign ABI.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     marshal = true,
+    /// )]
+    /// # */
+    /// ```
+    Marshal {
+        name: custom_token::Marshal,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Specifies other traits annotated with `#[thin_trait_object]` whose thin trait objects will be passed through the vtable as pointers, optionally along with the names of those thin trait objects.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     thin_traits(
+    ///         Node,
+    ///         other_module::Leaf = other_module::MyLeafObject,
+    ///     ),
+    /// )]
+    /// # */
+    /// ```
+    ThinTraits {
+        name: custom_token::ThinTraits,
+        #[allow(dead_code)]
+        paren: token::Paren,
+        thin_traits: Punctuated<ThinTrait, Token![,]>,
+    },
+    /// Specifies a value implementing `GlobalAlloc` which is to be used for allocating the thin trait objects instead of `Box`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     allocator = crate::pools::GRAPHICS,
+    /// )]
+    /// # */
+    /// ```
+    Allocator {
+        name: custom_token::Allocator,
+        #[allow(dead_code)]
+        eq: Token![=],
+        allocator: Path,
+    },
+    /// Sets whether the trait will be implemented for all types which implement its supertraits, instead of being implemented on the thin trait object.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     blanket_impl = true,
+    /// )]
+    /// # */
+    /// ```
+    BlanketImpl {
+        name: custom_token::BlanketImpl,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Specifies the path to the runtime crate, whose `ThinTraitObject` trait will be implemented for the thin trait object.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     runtime = thin_trait_object_runtime,
+    /// )]
+    /// # */
+    /// ```
+    Runtime {
+        name: custom_token::Runtime,
+        #[allow(dead_code)]
+        eq: Token![=],
+        path: Path,
+    },
+    /// Sets whether conversions between the thin trait object and `Box<dyn Trait>` will be generated, along with an implementation of the trait for the latter.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     dyn_conversions = true,
+    /// )]
+    /// # */
+    /// ```
+    DynConversions {
+        name: custom_token::DynConversions,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Sets whether the stack object and the borrowed thin trait object will be generated, which allow calls through the vtable without allocating.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     stack_objects = true,
+    /// )]
+    /// # */
+    /// ```
+    StackObjects {
+        name: custom_token::StackObjects,
+        #[allow(dead_code)]
+        eq: Token![=],
+        val: LitBool,
+    },
+    /// Sets whether the inline object will be generated, which stores small values without allocating.
+    ///
+    /// # Example
+    /// ```rust
+    /// # /*
+    /// #[thin_trait_object(
+    ///     inline_storage = true,
+    /// )]
+    /// # */
+    /// ```
+    InlineStorage {
+        name: custom_token::InlineStorage,
+        #[allow(dead_code)]
         eq: Token![=],
         val: LitBool,
     },
 }
+impl AttrOption {
+    /// Returns the span of the name of the option, used for errors which concern the option as a whole.
+    pub fn name_span(&self) -> Span {
+        match self {
+            Self::Vtable { name, .. } => name.0,
+            Self::InlineVtable { name, .. } => name.0,
+            Self::TraitObject { name, .. } => name.0,
+            Self::DropAbi { name, .. } => name.0,
+            Self::MarkerTraits { name, .. } => name.0,
+            Self::StoreLayout { name, .. } => name.0,
+            Self::Header { name, .. } => name.0,
+            Self::TraitDef { name, .. } => name.0,
+            Self::DropSlot { name, .. } => name.0,
+            Self::OnPanic { name, .. } => name.0,
+            Self::LowerResults { name, .. } => name.0,
+            Self::StrictFfi { name, .. } => name.0,
+            Self::Abi { name, .. } => name.0,
+            Self::Marshal { name, .. } => name.0,
+            Self::ThinTraits { name, .. } => name.0,
+            Self::Allocator { name, .. } => name.0,
+            Self::BlanketImpl { name, .. } => name.0,
+            Self::Runtime { name, .. } => name.0,
+            Self::DynConversions { name, .. } => name.0,
+            Self::StackObjects { name, .. } => name.0,
+            Self::InlineStorage { name, .. } => name.0,
+        }
+    }
+}
 impl Parse for AttrOption {
     fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
         let ident = input.parse::<Ident>()?;
         // see https://github.com/rust-lang/rust-clippy/issues/4637
-        #[allow(clippy::eval_order_dependence)]
+        #[allow(clippy::mixed_read_write_in_expression)]
         let option = match ident.to_string().borrow() {
             "vtable" => {
                 let inside_parens;
@@ -169,11 +455,109 @@ impl Parse for AttrOption {
                 eq: input.parse()?,
                 val: input.parse()?,
             },
+            "header" => {
+                let inside_parens;
+                Self::Header {
+                    name: custom_token::Header(ident.span()),
+                    paren: parenthesized!(inside_parens in input),
+                    ty: inside_parens.parse()?,
+                }
+            }
+            "trait_def" => {
+                let inside_parens;
+                Self::TraitDef {
+                    name: custom_token::TraitDef(ident.span()),
+                    paren: parenthesized!(inside_parens in input),
+                    additions: inside_parens.parse()?,
+                }
+            }
+            "drop_slot" => Self::DropSlot {
+                name: custom_token::DropSlot(ident.span()),
+                eq: input.parse()?,
+                slot: input.parse()?,
+            },
+            "on_panic" => {
+                let eq = input.parse()?;
+                let lit = input.parse()?;
+                if cfg!(not(feature = "std")) {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        "catching panics requires the `std` feature",
+                    ));
+                }
+                Self::OnPanic {
+                    name: custom_token::OnPanic(ident.span()),
+                    eq,
+                    policy: PanicPolicy::from_lit(&lit)?,
+                    lit,
+                }
+            }
+            "lower_results" => Self::LowerResults {
+                name: custom_token::LowerResults(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
+            "strict_ffi" => Self::StrictFfi {
+                name: custom_token::StrictFfi(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
+            "abi" => Self::Abi {
+                name: custom_token::Abi(ident.span()),
+                eq: input.parse()?,
+                abi: input.parse()?,
+            },
+            "marshal" => Self::Marshal {
+                name: custom_token::Marshal(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
+            "thin_traits" => {
+                let inside_parens;
+                Self::ThinTraits {
+                    name: custom_token::ThinTraits(ident.span()),
+                    paren: parenthesized!(inside_parens in input),
+                    thin_traits: inside_parens.call(Punctuated::parse_terminated)?,
+                }
+            }
+            "allocator" => Self::Allocator {
+                name: custom_token::Allocator(ident.span()),
+                eq: input.parse()?,
+                allocator: input.parse()?,
+            },
+            "blanket_impl" => Self::BlanketImpl {
+                name: custom_token::BlanketImpl(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
+            "runtime" => Self::Runtime {
+                name: custom_token::Runtime(ident.span()),
+                eq: input.parse()?,
+                path: input.parse()?,
+            },
+            "dyn_conversions" => Self::DynConversions {
+                name: custom_token::DynConversions(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
+            "stack_objects" => Self::StackObjects {
+                name: custom_token::StackObjects(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
+            "inline_storage" => Self::InlineStorage {
+                name: custom_token::InlineStorage(ident.span()),
+                eq: input.parse()?,
+                val: input.parse()?,
+            },
             _ => {
                 return Err(syn::Error::new_spanned(
                     ident,
                     "\
-expected `vtable`, `inline_vtable`, `trait_object`, `drop_abi` or `marker_traits`",
+expected `vtable`, `inline_vtable`, `trait_object`, `abi`, `drop_abi`, `marker_traits`, \
+`store_layout`, `header`, `trait_def`, `drop_slot`, `on_panic`, `lower_results`, `strict_ffi`, \
+`marshal`, `thin_traits`, `allocator`, `blanket_impl`, `runtime`, `dyn_conversions`, \
+`stack_objects` or `inline_storage`",
                 ));
             }
         };
@@ -205,6 +589,7 @@ pub mod custom_token {
 
     macro_rules! custom_tokens {
         ($name:ident, $string:literal) => (
+            #[allow(dead_code)]
             pub struct $name (pub Span);
             impl Parse for $name {
                 #[inline]
@@ -234,5 +619,20 @@ pub mod custom_token {
         (DropAbi, "drop_abi"),
         (MarkerTraits, "marker_traits"),
         (StoreLayout, "store_layout"),
+        (Header, "header"),
+        (TraitDef, "trait_def"),
+        (DropSlot, "drop_slot"),
+        (OnPanic, "on_panic"),
+        (LowerResults, "lower_results"),
+        (StrictFfi, "strict_ffi"),
+        (Abi, "abi"),
+        (Marshal, "marshal"),
+        (ThinTraits, "thin_traits"),
+        (Allocator, "allocator"),
+        (BlanketImpl, "blanket_impl"),
+        (Runtime, "runtime"),
+        (DynConversions, "dyn_conversions"),
+        (StackObjects, "stack_objects"),
+        (InlineStorage, "inline_storage"),
     }
 }
@@ -2,59 +2,75 @@
 
 use crate::{
     attr::StageStash,
-    vtable::{VtableFnArg, VtableItem},
+    closure::ClosureKind,
+    lowering::LoweredResult,
+    marshal::alloc_crate_name,
+    unwind::*,
+    vtable::{raw_receiver_type, VtableFnArg, VtableItem},
 };
 use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote, ToTokens};
-use syn::{token::Colon, Abi, BareFnArg, Path, Signature};
+use syn::{token::Colon, Path, Signature};
 
 pub fn generate_repr(
     stash: &mut StageStash,
     inline_vtable: bool,
     path_to_box: Path,
-    drop_abi: Option<&Abi>,
+    allocator: Option<&Path>,
     store_layout: bool,
 ) -> TokenStream {
     let StageStash {
         repr_name,
+        prefix_name,
         vtable_name,
         trait_name,
         vtable_items,
+        drop_slot,
+        header,
+        panic_policy,
         ..
     } = stash;
     let (vtable_contents, thunk_methods) = generate_vtable_and_thunks(
-        &trait_name,
-        &repr_name,
+        trait_name,
+        repr_name,
         vtable_items.iter().cloned(),
+        *panic_policy,
         |_| true, // TODO
     );
 
+    // The header, if any, is passed to the constructor as the second argument and becomes the
+    // first field of the repr struct.
+    let (header_field, header_ctor_arg, header_ctor_field) = match header {
+        Some(header) => (
+            quote! { __thintraitobjectmacro_repr_header: #header, },
+            quote! { __thintraitobjectmacro_arg1: #header, },
+            quote! { __thintraitobjectmacro_repr_header: __thintraitobjectmacro_arg1, },
+        ),
+        None => Default::default(),
+    };
     // Perform necessary branching depending on vtable style in advance.
-    let (vtable_field_type, ctor_val) = if inline_vtable {
+    let (vtable_field_type, vtable_field_val) = if inline_vtable {
         // The type of the vtable field is the vtable type's name itself,
         // so just get a token stream of it.
         let vtable_field_type = vtable_name.to_token_stream();
         // The constructor will memcpy the vtable into the repr struct.
-        let ctor_val = quote! {
-            Self {
-                __thintraitobjectmacro_repr_vtable: Self::__THINTRAITOBJECTMACRO_VTABLE,
-                __thintraitobjectmacro_repr_value: __thintraitobjectmacro_arg0,
-            }
-        };
-        (vtable_field_type, ctor_val)
+        let vtable_field_val = quote! { Self::__THINTRAITOBJECTMACRO_VTABLE };
+        (vtable_field_type, vtable_field_val)
     } else {
         // Here, we need to construct a reference-to-static type with the vtable typename.
         let vtable_field_type = quote! {
             &'static #vtable_name
         };
         // The constructor will borrow the static vtable.
-        let ctor_val = quote! {
-            Self {
-                __thintraitobjectmacro_repr_vtable: &Self::__THINTRAITOBJECTMACRO_VTABLE,
-                __thintraitobjectmacro_repr_value: __thintraitobjectmacro_arg0,
-            }
-        };
-        (vtable_field_type, ctor_val)
+        let vtable_field_val = quote! { &Self::__THINTRAITOBJECTMACRO_VTABLE };
+        (vtable_field_type, vtable_field_val)
+    };
+    let ctor_val = quote! {
+        Self {
+            #header_ctor_field
+            __thintraitobjectmacro_repr_vtable: #vtable_field_val,
+            __thintraitobjectmacro_repr_value: __thintraitobjectmacro_arg0,
+        }
     };
     let size_and_align = if store_layout {
         quote! {
@@ -64,10 +80,149 @@ pub fn generate_repr(
     } else {
         quote! {}
     };
+    let drop_name = &drop_slot.name;
+    let drop_entry = wrap_nullable(
+        quote! { Self :: __thintraitobjectmacro_repr_drop },
+        drop_slot.nullable,
+    );
+    let drop_abi = &drop_slot.abi;
+    let drop_arg_type = raw_receiver_type(drop_slot.raw_receiver.as_ref());
+    let alloc_crate = alloc_crate_name();
+    // The arguments of the constructor which are given back if the allocation fails.
+    let (give_back_type, give_back) = match header {
+        Some(header) => (
+            quote! { (#header, __ThinTraitObjectMacro_ReprGeneric0) },
+            quote! { (__thintraitobjectmacro_arg1, __thintraitobjectmacro_arg0) },
+        ),
+        None => (
+            quote! { __ThinTraitObjectMacro_ReprGeneric0 },
+            quote! { __thintraitobjectmacro_arg0 },
+        ),
+    };
+    let ReprAllocation {
+        raw_alloc,
+        raw_dealloc,
+        allocate,
+        try_allocate,
+        free,
+        take,
+    } = match allocator {
+        Some(allocator) => ReprAllocation::with_allocator(allocator, &ctor_val, &give_back),
+        None => ReprAllocation::with_box(&path_to_box, &ctor_val, &give_back),
+    };
+    let dealloc_guard = dealloc_guard(&raw_dealloc);
+    // Zero-sized values don't need an allocation if the vtable pointer is the only other thing in
+    // the object: a pointer to a constant vtable field is used instead, and the value is conjured
+    // from it, since any well-aligned pointer is valid for a zero-sized type.
+    let (
+        static_repr,
+        static_create,
+        static_try_create,
+        static_create_in_place,
+        destroy,
+        take_value,
+    ) = if header.is_none() {
+        let static_repr = quote! {
+            const __THINTRAITOBJECTMACRO_STATIC: bool =
+                ::core::mem::size_of::<__ThinTraitObjectMacro_ReprGeneric0>() == 0
+                    && ::core::mem::align_of::<__ThinTraitObjectMacro_ReprGeneric0>()
+                        <= ::core::mem::align_of::<#vtable_field_type>();
+            const __THINTRAITOBJECTMACRO_STATIC_REPR: &'static #vtable_field_type =
+                &#vtable_field_val;
+        };
+        let static_ptr = quote! {
+            Self::__THINTRAITOBJECTMACRO_STATIC_REPR as *const #vtable_field_type as *mut _
+        };
+        let static_create = quote! {
+            if Self::__THINTRAITOBJECTMACRO_STATIC {
+                ::core::mem::forget(__thintraitobjectmacro_arg0);
+                return #static_ptr;
+            }
+        };
+        let static_try_create = quote! {
+            if Self::__THINTRAITOBJECTMACRO_STATIC {
+                ::core::mem::forget(__thintraitobjectmacro_arg0);
+                return ::core::result::Result::Ok(#static_ptr);
+            }
+        };
+        let static_create_in_place = quote! {
+            if Self::__THINTRAITOBJECTMACRO_STATIC {
+                __thintraitobjectmacro_arg0(
+                    &mut *::core::ptr::NonNull::<
+                        ::core::mem::MaybeUninit<__ThinTraitObjectMacro_ReprGeneric0>
+                    >::dangling().as_ptr()
+                );
+                return #static_ptr;
+            }
+        };
+        let destroy = quote! {
+            if Self::__THINTRAITOBJECTMACRO_STATIC {
+                ::core::ptr::drop_in_place(
+                    &mut (*(__thintraitobjectmacro_arg0
+                        as *mut #repr_name<__ThinTraitObjectMacro_ReprGeneric0>
+                    )).__thintraitobjectmacro_repr_value
+                        as *mut __ThinTraitObjectMacro_ReprGeneric0
+                );
+            } else {
+                #free
+            }
+        };
+        let take_value = quote! {
+            if Self::__THINTRAITOBJECTMACRO_STATIC {
+                return ::core::ptr::read(
+                    &(*__thintraitobjectmacro_arg0).__thintraitobjectmacro_repr_value,
+                );
+            }
+        };
+        (
+            static_repr,
+            static_create,
+            static_try_create,
+            static_create_in_place,
+            destroy,
+            take_value,
+        )
+    } else {
+        (quote! {}, quote! {}, quote! {}, quote! {}, free, quote! {})
+    };
+    let header_write = if header.is_some() {
+        quote! {
+            ::core::ptr::addr_of_mut!((*ptr).__thintraitobjectmacro_repr_header)
+                .write(__thintraitobjectmacro_arg1);
+        }
+    } else {
+        quote! {}
+    };
+    // Dropping the value runs Rust code, which can always unwind.
+    let drop_body = wrap_thunk_body(
+        *panic_policy,
+        None,
+        drop_abi.as_ref(),
+        trait_name,
+        destroy,
+        None,
+    );
+    let panic_slot = generate_panic_slot(*panic_policy, trait_name);
+    // The trait object needs to find the vtable pointer without knowing the type of the value, so
+    // the part of the repr which precedes the value is also emitted as a separate struct.
+    let prefix = if header.is_some() {
+        quote! {
+            #[repr(C)]
+            struct #prefix_name {
+                #header_field
+                __thintraitobjectmacro_repr_vtable: #vtable_field_type,
+            }
+        }
+    } else {
+        quote! {}
+    };
     // Here comes the cluttered part: heavily prefixed names.
     let repr = quote! {
+        #panic_slot
+        #prefix
         #[repr(C)]
         struct #repr_name <__ThinTraitObjectMacro_ReprGeneric0: #trait_name> {
+            #header_field
             __thintraitobjectmacro_repr_vtable: #vtable_field_type,
             __thintraitobjectmacro_repr_value: __ThinTraitObjectMacro_ReprGeneric0,
         }
@@ -77,23 +232,78 @@ pub fn generate_repr(
             const __THINTRAITOBJECTMACRO_VTABLE: #vtable_name = #vtable_name {
                 #size_and_align
                 #vtable_contents
-                drop: Self :: __thintraitobjectmacro_repr_drop,
+                #drop_name: #drop_entry,
             };
+            #static_repr
 
+            // Creates the repr struct by value, for stack objects.
+            #[allow(dead_code)]
+            fn __thintraitobjectmacro_repr_new(
+                __thintraitobjectmacro_arg0: __ThinTraitObjectMacro_ReprGeneric0,
+                #header_ctor_arg
+            ) -> Self {
+                #ctor_val
+            }
             fn __thintraitobjectmacro_repr_create(
                 __thintraitobjectmacro_arg0: __ThinTraitObjectMacro_ReprGeneric0,
+                #header_ctor_arg
             ) -> *mut #vtable_name {
-                #path_to_box::into_raw(#path_to_box::new(#ctor_val)) as *mut _
+                #static_create
+                #allocate
+            }
+            fn __thintraitobjectmacro_repr_try_create(
+                __thintraitobjectmacro_arg0: __ThinTraitObjectMacro_ReprGeneric0,
+                #header_ctor_arg
+            ) -> ::core::result::Result<*mut #vtable_name, #give_back_type> {
+                #static_try_create
+                #try_allocate
             }
-            // Simple destructor which uses Box's internals to deallocate and
+            // Initializes the value in place, after the rest of the repr struct. The header is
+            // written last, so that it's dropped normally if the initializer panics.
+            unsafe fn __thintraitobjectmacro_repr_create_in_place(
+                __thintraitobjectmacro_arg0: impl ::core::ops::FnOnce(
+                    &mut ::core::mem::MaybeUninit<__ThinTraitObjectMacro_ReprGeneric0>
+                ),
+                #header_ctor_arg
+            ) -> *mut #vtable_name {
+                #static_create_in_place
+                let layout = ::core::alloc::Layout::new::<Self>();
+                let ptr = #raw_alloc as *mut Self;
+                if ptr.is_null() {
+                    ::#alloc_crate::alloc::handle_alloc_error(layout);
+                }
+                // Frees the memory if the initializer panics.
+                #dealloc_guard
+                let guard = Guard(ptr as *mut u8, layout);
+                ::core::ptr::addr_of_mut!((*ptr).__thintraitobjectmacro_repr_vtable)
+                    .write(#vtable_field_val);
+                __thintraitobjectmacro_arg0(
+                    &mut *(::core::ptr::addr_of_mut!((*ptr).__thintraitobjectmacro_repr_value)
+                        as *mut ::core::mem::MaybeUninit<__ThinTraitObjectMacro_ReprGeneric0>)
+                );
+                ::core::mem::forget(guard);
+                #header_write
+                ptr as *mut _
+            }
+            // Moves the value out of the object, freeing it without running the destructor.
+            #[allow(dead_code)]
+            unsafe fn __thintraitobjectmacro_repr_take(
+                __thintraitobjectmacro_arg0: *mut Self,
+            ) -> __ThinTraitObjectMacro_ReprGeneric0 {
+                #take_value
+                #take
+            }
+            // Simple destructor which uses Box's internals, or the allocator, to deallocate and
             // drop the value as necessary.
             unsafe #drop_abi fn __thintraitobjectmacro_repr_drop(
-                __thintraitobjectmacro_arg0: *mut ::core::ffi::c_void,
+                __thintraitobjectmacro_arg0: #drop_arg_type,
             ) {
-                let _ = #path_to_box::from_raw(
-                    __thintraitobjectmacro_arg0
-                        as *mut #repr_name<__ThinTraitObjectMacro_ReprGeneric0>
-                );
+                #drop_body
+            }
+            // Drops the repr struct without freeing it, for inline objects.
+            #[allow(dead_code)]
+            unsafe fn __thintraitobjectmacro_repr_drop_in_place(__thintraitobjectmacro_arg0: *mut ()) {
+                ::core::ptr::drop_in_place(__thintraitobjectmacro_arg0 as *mut Self);
             }
             #thunk_methods
         }
@@ -105,17 +315,141 @@ pub fn generate_repr(
 pub fn repr_name_from_trait_name(trait_name: Ident) -> Ident {
     format_ident!("__ThinTraitObjectMacro_ReprFor{}", trait_name)
 }
+#[inline]
+pub fn prefix_name_from_trait_name(trait_name: Ident) -> Ident {
+    format_ident!("__ThinTraitObjectMacro_PrefixFor{}", trait_name)
+}
+
+/// The code which allocates and frees the repr struct, all of which is placed in its impl block.
+struct ReprAllocation {
+    /// Allocates uninitialized memory with the layout in the `layout` variable.
+    raw_alloc: TokenStream,
+    /// Frees the memory in the `ptr` variable, which has the layout in the `layout` variable.
+    raw_dealloc: TokenStream,
+    /// Allocates the repr struct, aborting on failure.
+    allocate: TokenStream,
+    /// Allocates the repr struct, giving the arguments of the constructor back on failure.
+    try_allocate: TokenStream,
+    /// Drops the repr struct pointed to by the first argument and frees it.
+    free: TokenStream,
+    /// Moves the value out of the repr struct pointed to by the first argument and frees it.
+    take: TokenStream,
+}
+impl ReprAllocation {
+    /// Allocation with `Box`, which uses the global allocator. `try_allocate` uses the global
+    /// allocator directly, since `Box` has no stable fallible constructor, which is allowed because
+    /// `Box` uses the layout of the contained type.
+    fn with_box(path_to_box: &Path, ctor_val: &TokenStream, give_back: &TokenStream) -> Self {
+        let alloc_crate = alloc_crate_name();
+        let raw_alloc = quote! { ::#alloc_crate::alloc::alloc(layout) };
+        Self {
+            try_allocate: try_allocate(&raw_alloc, ctor_val, give_back),
+            raw_alloc,
+            raw_dealloc: quote! { ::#alloc_crate::alloc::dealloc(ptr, layout) },
+            allocate: quote! {
+                #path_to_box::into_raw(#path_to_box::new(#ctor_val)) as *mut _
+            },
+            free: quote! {
+                let _ = #path_to_box::from_raw(__thintraitobjectmacro_arg0 as *mut Self);
+            },
+            take: quote! {
+                #path_to_box::from_raw(__thintraitobjectmacro_arg0).__thintraitobjectmacro_repr_value
+            },
+        }
+    }
+    /// Allocation with a value implementing `GlobalAlloc`. The repr struct is moved out before
+    /// releasing the memory, so that the memory is not leaked if dropping the value panics.
+    fn with_allocator(allocator: &Path, ctor_val: &TokenStream, give_back: &TokenStream) -> Self {
+        let alloc_crate = alloc_crate_name();
+        let raw_dealloc = quote! { ::core::alloc::GlobalAlloc::dealloc(&#allocator, ptr, layout) };
+        let dealloc_guard = dealloc_guard(&raw_dealloc);
+        let read_and_release = quote! {
+            let ptr = __thintraitobjectmacro_arg0 as *mut Self;
+            let repr = ::core::ptr::read(ptr);
+            ::core::alloc::GlobalAlloc::dealloc(
+                &#allocator,
+                ptr as *mut u8,
+                ::core::alloc::Layout::new::<Self>(),
+            );
+        };
+        let raw_alloc = quote! { ::core::alloc::GlobalAlloc::alloc(&#allocator, layout) };
+        Self {
+            allocate: quote! {
+                let layout = ::core::alloc::Layout::new::<Self>();
+                unsafe {
+                    let ptr = #raw_alloc as *mut Self;
+                    if ptr.is_null() {
+                        ::#alloc_crate::alloc::handle_alloc_error(layout);
+                    }
+                    ::core::ptr::write(ptr, #ctor_val);
+                    ptr as *mut _
+                }
+            },
+            try_allocate: try_allocate(&raw_alloc, ctor_val, give_back),
+            raw_alloc,
+            raw_dealloc,
+            // The value is dropped in place, since it might be pinned.
+            free: quote! {
+                #dealloc_guard
+                let _guard = Guard(
+                    __thintraitobjectmacro_arg0 as *mut u8,
+                    ::core::alloc::Layout::new::<Self>(),
+                );
+                ::core::ptr::drop_in_place(__thintraitobjectmacro_arg0 as *mut Self);
+            },
+            take: quote! {
+                #read_and_release
+                repr.__thintraitobjectmacro_repr_value
+            },
+        }
+    }
+}
+/// Defines a `Guard(*mut u8, Layout)` struct which frees the memory with `raw_dealloc` when dropped.
+fn dealloc_guard(raw_dealloc: &TokenStream) -> TokenStream {
+    quote! {
+        struct Guard(*mut u8, ::core::alloc::Layout);
+        impl ::core::ops::Drop for Guard {
+            fn drop(&mut self) {
+                let (ptr, layout) = (self.0, self.1);
+                unsafe { #raw_dealloc }
+            }
+        }
+    }
+}
+/// Allocates the memory for the repr struct with `raw_alloc`, which has the layout in scope, and
+/// moves the repr struct into it if the allocation succeeded.
+fn try_allocate(
+    raw_alloc: &TokenStream,
+    ctor_val: &TokenStream,
+    give_back: &TokenStream,
+) -> TokenStream {
+    quote! {
+        let layout = ::core::alloc::Layout::new::<Self>();
+        let ptr = unsafe { #raw_alloc } as *mut Self;
+        if ptr.is_null() {
+            return ::core::result::Result::Err(#give_back);
+        }
+        unsafe { ::core::ptr::write(ptr, #ctor_val) };
+        ::core::result::Result::Ok(ptr as *mut _)
+    }
+}
 
 fn generate_vtable_and_thunks(
     trait_name: &Ident,
     repr_name: &Ident,
     vtable_entries: impl IntoIterator<Item = VtableItem>,
+    panic_policy: Option<PanicPolicy>,
     mut double_hop_predicate: impl FnMut(&VtableItem) -> bool,
 ) -> (TokenStream, TokenStream) {
     let mut vtable_contents = TokenStream::new();
     let mut thunk_methods = TokenStream::new();
     for mut entry in vtable_entries {
         let double_hop = double_hop_predicate(&entry);
+        // The raw form has the ABI of the vtable entry, which might differ from that of the method.
+        let method_abi = entry.abi.clone();
+        let input_count = entry.inputs.len();
+        // Has to be checked before the receiver is replaced with a pointer.
+        let pinned_receiver = entry.pinned_receiver();
 
         let has_receiver = entry.make_raw();
         if has_receiver {
@@ -123,16 +457,38 @@ fn generate_vtable_and_thunks(
         }
         // Create the list of arguments decorated with the collision-avoiding
         // names. Using mixed-site hygeine could be a better solution.
-        let mut argument_counter = 1_u32;
-        let thunk_call_args = entry.inputs.clone().into_iter().skip(1).map(|x| {
-            let arg = to_nth_thunk_arg(x, argument_counter);
-            argument_counter += 1;
-            arg
-        });
+        let mut thunk_call_args = name_thunk_args(&mut entry);
+        // The first one is the receiver.
+        thunk_call_args.remove(0);
+        // The out-pointer of a lowered result is not passed on to the method.
+        let lowered_result = entry.lowered_result.clone();
+        let out = match &lowered_result {
+            Some(lowered_result) if !lowered_result.ok_is_unit() => thunk_call_args.pop(),
+            _ => None,
+        };
+        // Marshalled arguments take up more than one argument of the thunk and have to be converted.
+        let mut thunk_call_args = thunk_call_args.into_iter();
+        let thunk_call_args = (1..input_count)
+            .map(|index| match entry.marshalled_arg(index) {
+                Some(marshal) => {
+                    let raw = thunk_call_args
+                        .by_ref()
+                        .take(marshal.raw_arg_count())
+                        .collect::<Vec<_>>();
+                    marshal.lift_arg(&raw)
+                }
+                None => thunk_call_args.next().unwrap().into_token_stream(),
+            })
+            .collect::<Vec<_>>();
+        let marshalled_output = entry.marshalled_output.clone();
+        let closure = entry.closure;
+        let forwarded = entry.forwarded;
+        let slot_abi = entry.effective_slot_abi().cloned();
 
         if double_hop {
             // Clone this out before handing them over to into_signature().
             let name = entry.name.clone();
+            let nullable = entry.nullable;
 
             let thunk_name = format_ident!("__thintraitobjectmacro_thunk_{}", &entry.name);
             let thunk_signature = {
@@ -143,53 +499,96 @@ fn generate_vtable_and_thunks(
 
             // Remember that this gets called in a loop, so we add one vtable
             // constructor entry for every vtable entry.
-            write_vtable_thunk_entry(&name, &thunk_name, &mut vtable_contents);
+            write_vtable_thunk_entry(&name, &thunk_name, nullable, &mut vtable_contents);
 
             // Generate the thunks, again, one for every vtable entry. Those are
             // pretty simple, actually: just unsafely convert the pointer to a
             // reference to the repr struct and call the appropriate method,
             // offsetting into the actual value.
-            write_thunk(
-                &name,
-                &repr_name,
-                thunk_signature,
-                thunk_call_args,
-                &mut thunk_methods,
+            let value_of = |ptr: TokenStream| {
+                quote! {
+                    (
+                        *(#ptr as *mut #repr_name<__ThinTraitObjectMacro_ReprGeneric0>)
+                    ).__thintraitobjectmacro_repr_value
+                }
+            };
+            let value = value_of(quote! { __thintraitobjectmacro_arg0 });
+            let call = match (closure, pinned_receiver, forwarded) {
+                (_, _, Some(forwarded)) => {
+                    forwarded.thunk_call(value_of, &thunk_call_args, slot_abi.as_ref())
+                }
+                // The value never moves out of the allocation until it's dropped, so it's
+                // pinned for as long as the object exists.
+                (None, Some(mutability), None) => quote! {
+                    ::core::pin::Pin::new_unchecked(&#mutability #value)
+                        .#name(#(#thunk_call_args),*)
+                },
+                (None, None, None) => quote! {
+                    #value.#name(#(#thunk_call_args),*)
+                },
+                (Some(ClosureKind::Fn), ..) | (Some(ClosureKind::FnMut), ..) => quote! {
+                    (#value)(#(#thunk_call_args),*)
+                },
+                // The closure is moved out of the object, which is then freed without dropping
+                // it, so the destructor must not be called afterwards.
+                (Some(ClosureKind::FnOnce), ..) => quote! {
+                    (
+                        #repr_name::<__ThinTraitObjectMacro_ReprGeneric0>::__thintraitobjectmacro_repr_take(
+                            __thintraitobjectmacro_arg0 as *mut _
+                        )
+                    )(#(#thunk_call_args),*)
+                },
+            };
+            let (body, panic_return) = match &lowered_result {
+                Some(lowered_result) => (
+                    lowered_result.lower(call, out.as_ref()),
+                    Some(LoweredResult::panic_return()),
+                ),
+                None => match &marshalled_output {
+                    Some(marshal) => (marshal.lower_owned(call), None),
+                    None => (call, None),
+                },
+            };
+            let body = wrap_thunk_body(
+                panic_policy,
+                method_abi.as_ref(),
+                thunk_signature.abi.as_ref(),
+                trait_name,
+                body,
+                panic_return,
             );
+            write_thunk(thunk_signature, body, &mut thunk_methods);
         } else {
-            write_vtable_single_hop_entry(&entry.name, &trait_name, &mut vtable_contents);
+            write_vtable_single_hop_entry(&entry.name, trait_name, &mut vtable_contents);
         }
     }
     (vtable_contents, thunk_methods)
 }
 
-fn write_vtable_thunk_entry(name: &Ident, val: &Ident, out: &mut TokenStream) {
+fn write_vtable_thunk_entry(name: &Ident, val: &Ident, nullable: bool, out: &mut TokenStream) {
+    let val = wrap_nullable(quote! { Self :: #val }, nullable);
     (quote! {
-        #name: Self :: #val,
+        #name: #val,
     })
     .to_tokens(out);
 }
+fn wrap_nullable(val: TokenStream, nullable: bool) -> TokenStream {
+    if nullable {
+        quote! { ::core::option::Option::Some(#val) }
+    } else {
+        val
+    }
+}
 fn write_vtable_single_hop_entry(name: &Ident, trait_name: &Ident, out: &mut TokenStream) {
     (quote! {
         #name: <__ThinTraitObjectMacro_ReprGeneric0 as #trait_name> :: #name,
     })
     .to_tokens(out);
 }
-fn write_thunk(
-    name: &Ident,
-    repr_name: &Ident,
-    signature: Signature,
-    args: impl IntoIterator<Item = BareFnArg>,
-    out: &mut TokenStream,
-) {
-    let args = args.into_iter().map(|arg| arg.name.unwrap().0);
+fn write_thunk(signature: Signature, body: TokenStream, out: &mut TokenStream) {
     (quote! {
         #signature {
-            (
-                *(__thintraitobjectmacro_arg0
-                    as *mut #repr_name<__ThinTraitObjectMacro_ReprGeneric0>
-                )
-            ).__thintraitobjectmacro_repr_value.#name(#(#args),*)
+            #body
         }
     })
     .to_tokens(out);
@@ -198,9 +597,20 @@ fn write_thunk(
 fn nth_arg(n: u32) -> Ident {
     format_ident!("__thintraitobjectmacro_arg{}", n)
 }
-/// Transforms a VtableFnArg to an argument to a thunk.
-fn to_nth_thunk_arg(arg: VtableFnArg, n: u32) -> BareFnArg {
-    let mut arg = arg.into_bare_arg_with_ptr_receiver();
-    arg.name = Some(arg.name.unwrap_or_else(|| (nth_arg(n), Colon::default())));
-    arg
+/// Names the unnamed arguments of a raw vtable entry after their position, returning the names of all arguments.
+fn name_thunk_args(entry: &mut VtableItem) -> Vec<Ident> {
+    let mut names = Vec::with_capacity(entry.inputs.len());
+    for (n, input) in entry.inputs.iter_mut().enumerate() {
+        let arg = match input {
+            VtableFnArg::Normal(arg) => arg,
+            VtableFnArg::Receiver(..) | VtableFnArg::PinnedReceiver(..) => {
+                unreachable!("the entry was made raw")
+            }
+        };
+        let (name, _) = arg
+            .name
+            .get_or_insert_with(|| (nth_arg(n as u32), Colon::default()));
+        names.push(name.clone());
+    }
+    names
 }
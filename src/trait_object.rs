@@ -1,26 +1,41 @@
 //! Generates the owned trait object struct. Not to be confused with the representation struct.
 
-use proc_macro2::TokenStream;
+use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote, ToTokens};
-use syn::{punctuated::Punctuated, token, Attribute, FnArg, Path, Visibility};
+use syn::{parse_quote, punctuated::Punctuated, token, Attribute, FnArg, Path, Type, Visibility};
 
-use crate::{attr::StageStash, marker_traits::MarkerTrait, vtable::VtableItem};
+use crate::{
+    attr::StageStash,
+    closure::ClosureKind,
+    forward::generate_forwarded_impls,
+    marker_traits::MarkerTrait,
+    unwind::*,
+    vtable::{raw_receiver_type, DropSlot, VtableItem},
+};
 
 pub fn generate_trait_object<'a>(
     stash: &mut StageStash,
     visibility: Visibility,
     inline_vtable: bool,
     has_static_bound: bool,
+    blanket_impl: bool,
     attributes: impl IntoIterator<Item = &'a Attribute> + Clone,
     markers: impl IntoIterator<Item = &'a MarkerTrait>,
 ) -> syn::Result<TokenStream> {
     let StageStash {
         trait_name,
         repr_name,
+        prefix_name,
         vtable_name,
         trait_object_name,
         vtable_items,
-        ..
+        drop_slot,
+        header,
+        panic_policy,
+        forwarded_traits,
+        runtime,
+        stack_objects,
+        inline_storage,
     } = stash;
     let trait_object_name_as_path = trait_object_name.clone().into();
     #[derive(Copy, Clone)]
@@ -38,34 +53,88 @@ pub fn generate_trait_object<'a>(
             let implementor = if self.elided_lifetime {
                 quote! {#implementor <'_>}
             } else {
-                quote! {implementor} // Performance is my passion
+                quote! {#implementor}
             };
-            self.marker_trait.as_impl_for(&implementor)
+            self.marker_trait.as_impl_for(&quote! {}, &implementor)
         }
     }
-    struct VtableItemToImplThunk(VtableItem);
-    impl ToTokens for VtableItemToImplThunk {
+    struct VtableItemToImplThunk<'a> {
+        item: VtableItem,
+        trait_name: &'a Ident,
+        panic_policy: Option<PanicPolicy>,
+    }
+    impl VtableItemToImplThunk<'_> {
+        /// Closures are called through inherent methods, since the closure traits cannot be implemented on stable Rust.
+        fn is_inherent(&self) -> bool {
+            self.item.closure.is_some()
+        }
+    }
+    impl ToTokens for VtableItemToImplThunk<'_> {
         fn to_tokens(&self, token_stream: &mut TokenStream) {
             let signature = self
-                .0
+                .item
                 .clone()
                 .into_signature(|x| format_ident!("__arg{}", x));
             let call_args = signature
                 .inputs
                 .clone()
                 .into_iter()
-                .map(|param| match param {
-                    FnArg::Typed(param) => param.pat.into_token_stream(),
+                .enumerate()
+                .map(|(index, param)| match param {
+                    FnArg::Typed(..) if index == 0 && self.item.pinned_receiver().is_some() => {
+                        quote! { self.as_raw() as _ }
+                    }
+                    FnArg::Typed(param) => match self.item.marshalled_arg(index) {
+                        Some(marshal) => marshal.lower_arg(param.pat.into_token_stream()),
+                        None => param.pat.into_token_stream(),
+                    },
+                    // Consuming the object transfers the ownership to the vtable entry.
+                    FnArg::Receiver(receiver) if receiver.reference.is_none() => quote! {
+                        self.into_raw() as _
+                    },
                     FnArg::Receiver(..) => quote! {
-                        self.0.as_ptr() as *mut _
+                        self.as_raw() as _
                     },
                 })
                 .collect::<Punctuated<_, token::Comma>>();
             let call_name = signature.ident.clone();
+            let function =
+                unwrap_nullable(quote! { (self.vtable()).#call_name }, self.item.nullable);
+            let resume = resume_stored_panic(
+                self.panic_policy,
+                self.item.abi.as_ref(),
+                self.item.effective_slot_abi(),
+                self.trait_name,
+            );
+            let body = match &self.item.lowered_result {
+                Some(lowered_result) => {
+                    lowered_result.lift(function, call_args.into_token_stream(), resume)
+                }
+                None => {
+                    let ret = match &self.item.marshalled_output {
+                        Some(marshal) => marshal.lift_owned(quote! { ret }),
+                        None => quote! { ret },
+                    };
+                    quote! {
+                        let ret = (#function)(#call_args);
+                        #resume
+                        #ret
+                    }
+                }
+            };
+            let doc_and_visibility = if self.is_inherent() {
+                quote! {
+                    /// Calls the contained closure.
+                    #[inline]
+                    pub
+                }
+            } else {
+                quote! {}
+            };
             (quote! {
-                #signature {
+                #doc_and_visibility #signature {
                     unsafe {
-                        ((self.vtable()).#call_name)(#call_args)
+                        #body
                     }
                 }
             })
@@ -73,44 +142,506 @@ pub fn generate_trait_object<'a>(
         }
     }
 
+    struct VtableItemToCallbackGetters<'a> {
+        item: &'a VtableItem,
+        drop_slot: &'a DropSlot,
+    }
+    impl ToTokens for VtableItemToCallbackGetters<'_> {
+        fn to_tokens(&self, token_stream: &mut TokenStream) {
+            let item = self.item;
+            // A consuming entry frees the object, so the destructor could not be called afterwards.
+            let receiver = match item.receiver() {
+                Some(receiver) if item.has_foreign_abi() && receiver.reference.is_some() => {
+                    receiver
+                }
+                _ => return,
+            };
+            let self_ref = if receiver.mutability.is_some() {
+                quote! { &mut self }
+            } else {
+                quote! { &self }
+            };
+            let name = &item.name;
+            let as_name = format_ident!("as_c_callback_{}", name);
+            let into_name = format_ident!("into_c_callback_{}", name);
+            let as_doc = format!(
+                "\
+Returns the `{}` method as a C callback, along with the pointer which is to be passed to it as \
+the first argument.",
+                name,
+            );
+            let into_doc = format!(
+                "\
+Releases ownership of the trait object, returning the `{}` method as a C callback, the pointer \
+which is to be passed to it as the first argument, and the destructor which is to be called with \
+that pointer once the callback is no longer needed.",
+                name,
+            );
+            let function_type = item.to_raw_function_pointer();
+            let receiver_type = raw_receiver_type(item.raw_receiver.as_ref());
+            let function = unwrap_nullable(quote! { self.vtable().#name }, item.nullable);
+            let drop_type = self.drop_slot.to_function_pointer();
+            let drop_function = {
+                let drop_name = &self.drop_slot.name;
+                unwrap_nullable(quote! { self.vtable().#drop_name }, self.drop_slot.nullable)
+            };
+            (quote! {
+                #[doc = #as_doc]
+                ///
+                /// The callback may only be called while the trait object stays borrowed.
+                #[inline]
+                pub fn #as_name(#self_ref) -> (#function_type, #receiver_type) {
+                    (#function, self.0.as_ptr() as _)
+                }
+                #[doc = #into_doc]
+                #[inline]
+                pub fn #into_name(self) -> (#function_type, #receiver_type, #drop_type) {
+                    let function = #function;
+                    let destructor = #drop_function;
+                    (function, self.into_raw() as _, destructor)
+                }
+            })
+            .to_tokens(token_stream);
+        }
+    }
+
     attributes
         .clone()
         .into_iter()
         .try_for_each(check_attribute)?;
     let attributes = attributes.into_iter();
-    let marker_impls = markers.into_iter().map(|marker_trait| MarkerToImpl {
+    let markers = markers.into_iter().collect::<Vec<_>>();
+    let marker_impls = markers.iter().map(|&marker_trait| MarkerToImpl {
         marker_trait,
         implementor: &trait_object_name_as_path,
         elided_lifetime: !has_static_bound,
     });
 
-    let impl_thunks = vtable_items.iter().cloned().map(VtableItemToImplThunk);
+    let (inherent_thunks, impl_thunks): (Vec<_>, Vec<_>) = vtable_items
+        .iter()
+        .filter(|item| item.forwarded.is_none())
+        .cloned()
+        .map(|item| VtableItemToImplThunk {
+            item,
+            trait_name,
+            panic_policy: *panic_policy,
+        })
+        .partition(VtableItemToImplThunk::is_inherent);
+    let callback_getters = vtable_items
+        .iter()
+        .filter(|item| item.forwarded.is_none())
+        .map(|item| VtableItemToCallbackGetters { item, drop_slot });
+    let has_pinned_receivers = vtable_items
+        .iter()
+        .any(|item| item.pinned_receiver().is_some());
+    // A pinned reference to the thin trait object has to imply that the value is pinned.
+    let phantom_pinned = if has_pinned_receivers {
+        quote! { ::core::marker::PhantomPinned }
+    } else {
+        quote! {}
+    };
     let (phantomdata, generics, creation_bound, impl_elided_lifetime) = if has_static_bound {
         let phantomdata = quote! {
-            ::core::marker::PhantomData<&'static ()>
+            ::core::marker::PhantomData<(&'static (), #phantom_pinned)>
         };
         // Those three are empty, so use the tuple Default impl to write this concisely
         let (generics, creation_bound, impl_elided_lifetime) = Default::default();
         (phantomdata, generics, creation_bound, impl_elided_lifetime)
     } else {
         let phantomdata = quote! {
-            ::core::marker::PhantomData<&'inner ()>
+            ::core::marker::PhantomData<(&'inner (), #phantom_pinned)>
         };
         let generics = quote! { <'inner> };
         let creation_bound = quote! { 'inner };
         let impl_elided_lifetime = quote! { <'_> };
         (phantomdata, generics, creation_bound, impl_elided_lifetime)
     };
-    let vtable_getter_impl = {
+    // Code which passes the raw pointer to C needs to know where it points.
+    let raw_pointer_doc = if header.is_some() {
+        quote! {
+            ///
+            /// The pointer points to the header, which precedes the vtable pointer, rather than to the vtable pointer itself.
+        }
+    } else {
+        quote! {}
+    };
+    let vtable_getter_impl = if header.is_some() {
+        // The vtable pointer is not at the start of the object, so go through the prefix struct
+        // to have the compiler figure out the offset.
+        let borrow = if inline_vtable {
+            quote! { & }
+        } else {
+            quote! {}
+        };
+        quote! {
+            unsafe {
+                #borrow (*(self.as_raw() as *const #prefix_name)).__thintraitobjectmacro_repr_vtable
+            }
+        }
+    } else {
         let vtable_pointer_cast = if inline_vtable {
             quote! { as *mut }
         } else {
             quote! { as *mut &'static }
         };
         quote! {
-            unsafe { &*(self.0.as_ptr() #vtable_pointer_cast #vtable_name) }
+            unsafe { &*(self.as_raw() #vtable_pointer_cast #vtable_name) }
         }
     };
+    let (constructors, default_header_constructors, header_getters) = if let Some(header) = header {
+        // A plain `#header: Default` bound would be an error for a header type which doesn't
+        // implement `Default`, while a higher-ranked one only makes the constructors unavailable.
+        let default_header_constructors = quote! {
+            impl #generics #trait_object_name #generics
+            where
+                for<'__thintraitobjectmacro_header> #header: ::core::default::Default,
+            {
+                /// Constructs a boxed thin trait object from a type implementing the trait, using the default value for the header.
+                #[inline]
+                pub fn new<
+                    T: #trait_name + Sized + #creation_bound
+                    >(val: T) -> Self {
+                        Self::new_with_header(::core::default::Default::default(), val)
+                }
+                /// Constructs a boxed thin trait object from a type implementing the trait, using the default value for the header, or gives the value back if the allocation fails.
+                #[inline]
+                pub fn try_new<
+                    T: #trait_name + Sized + #creation_bound
+                    >(val: T) -> ::core::result::Result<Self, T> {
+                        Self::try_new_with_header(::core::default::Default::default(), val)
+                            .map_err(|(_, val)| val)
+                }
+                /// Constructs a boxed thin trait object from the return value of a closure, using the default value for the header. The closure is called once the allocation is made, which lets the compiler write the value directly into it rather than moving it there from the stack, but that is only an optimization opportunity; use [`new_in_place`] to guarantee it.
+                ///
+                /// [`new_in_place`]: #method.new_in_place " "
+                #[inline]
+                pub fn new_with<
+                    T: #trait_name + Sized + #creation_bound,
+                    F: ::core::ops::FnOnce() -> T,
+                    >(f: F) -> Self {
+                        unsafe {
+                            Self::new_in_place(|slot: &mut ::core::mem::MaybeUninit<T>| {
+                                slot.as_mut_ptr().write(f())
+                            })
+                        }
+                }
+                /// Constructs a boxed thin trait object by initializing the value in place inside the allocation, using the default value for the header.
+                ///
+                /// # Safety
+                /// The closure must fully initialize the value before returning.
+                #[inline]
+                pub unsafe fn new_in_place<
+                    T: #trait_name + Sized + #creation_bound
+                    >(init: impl ::core::ops::FnOnce(&mut ::core::mem::MaybeUninit<T>)) -> Self {
+                        Self::new_in_place_with_header(::core::default::Default::default(), init)
+                }
+            }
+        };
+        let constructors = quote! {
+            /// Constructs a boxed thin trait object from a type implementing the trait and the header which is to precede the vtable pointer.
+            #[inline]
+            pub fn new_with_header<
+                T: #trait_name + Sized + #creation_bound
+                >(header: #header, val: T) -> Self {
+                    unsafe {
+                        Self::from_raw(
+                            #repr_name::__thintraitobjectmacro_repr_create(val, header) as *mut _
+                        )
+                    }
+            }
+            /// Constructs a boxed thin trait object from a type implementing the trait and the header which is to precede the vtable pointer, or gives both back if the allocation fails.
+            #[inline]
+            pub fn try_new_with_header<
+                T: #trait_name + Sized + #creation_bound
+                >(header: #header, val: T) -> ::core::result::Result<Self, (#header, T)> {
+                    #repr_name::__thintraitobjectmacro_repr_try_create(val, header)
+                        .map(|ptr| unsafe { Self::from_raw(ptr as *mut _) })
+            }
+            /// Constructs a boxed thin trait object by initializing the value in place inside the allocation, with the header which is to precede the vtable pointer.
+            ///
+            /// # Safety
+            /// The closure must fully initialize the value before returning.
+            #[inline]
+            pub unsafe fn new_in_place_with_header<
+                T: #trait_name + Sized + #creation_bound
+                >(
+                    header: #header,
+                    init: impl ::core::ops::FnOnce(&mut ::core::mem::MaybeUninit<T>),
+                ) -> Self {
+                    Self::from_raw(
+                        #repr_name::__thintraitobjectmacro_repr_create_in_place(init, header) as *mut _
+                    )
+            }
+        };
+        let header_getters = quote! {
+            /// Retrieves the header which precedes the vtable pointer in the contained trait object.
+            #[inline]
+            pub fn header(&self) -> &#header {
+                unsafe { &(*(self.0.as_ptr() as *const #prefix_name)).__thintraitobjectmacro_repr_header }
+            }
+            /// Retrieves a mutable reference to the header which precedes the vtable pointer in the contained trait object.
+            #[inline]
+            pub fn header_mut(&mut self) -> &mut #header {
+                unsafe { &mut (*(self.0.as_ptr() as *mut #prefix_name)).__thintraitobjectmacro_repr_header }
+            }
+        };
+        (constructors, default_header_constructors, header_getters)
+    } else {
+        let constructors = quote! {
+            /// Constructs a boxed thin trait object from a type implementing the trait.
+            ///
+            /// Zero-sized types are not allocated, unless their alignment is greater than that of the vtable pointer.
+            #[inline]
+            pub fn new<
+                T: #trait_name + Sized + #creation_bound
+                >(val: T) -> Self {
+                    unsafe { Self::from_raw(#repr_name::__thintraitobjectmacro_repr_create(val) as *mut _) }
+            }
+            /// Constructs a boxed thin trait object from a type implementing the trait, or gives the value back if the allocation fails.
+            ///
+            /// Zero-sized types are not allocated unless their alignment is greater than that of the vtable pointer, so this never fails for them.
+            #[inline]
+            pub fn try_new<
+                T: #trait_name + Sized + #creation_bound
+                >(val: T) -> ::core::result::Result<Self, T> {
+                    #repr_name::__thintraitobjectmacro_repr_try_create(val)
+                        .map(|ptr| unsafe { Self::from_raw(ptr as *mut _) })
+            }
+            /// Constructs a boxed thin trait object from the return value of a closure. The closure is called once the allocation is made, which lets the compiler write the value directly into it rather than moving it there from the stack, but that is only an optimization opportunity; use [`new_in_place`] to guarantee it.
+            ///
+            /// [`new_in_place`]: #method.new_in_place " "
+            #[inline]
+            pub fn new_with<
+                T: #trait_name + Sized + #creation_bound,
+                F: ::core::ops::FnOnce() -> T,
+                >(f: F) -> Self {
+                    unsafe {
+                        Self::new_in_place(|slot: &mut ::core::mem::MaybeUninit<T>| {
+                            slot.as_mut_ptr().write(f())
+                        })
+                    }
+            }
+            /// Constructs a boxed thin trait object by initializing the value in place inside the allocation.
+            ///
+            /// # Safety
+            /// The closure must fully initialize the value before returning.
+            #[inline]
+            pub unsafe fn new_in_place<
+                T: #trait_name + Sized + #creation_bound
+                >(init: impl ::core::ops::FnOnce(&mut ::core::mem::MaybeUninit<T>)) -> Self {
+                    Self::from_raw(#repr_name::__thintraitobjectmacro_repr_create_in_place(init) as *mut _)
+            }
+        };
+        (constructors, quote! {}, quote! {})
+    };
+    let drop_function = unwrap_nullable(
+        {
+            let drop_name = &drop_slot.name;
+            quote! { self.vtable().#drop_name }
+        },
+        drop_slot.nullable,
+    );
+    let (drop_resume, take_panic) = if *panic_policy == Some(PanicPolicy::CatchAndStore) {
+        let drop_resume =
+            resume_stored_panic(*panic_policy, None, drop_slot.abi.as_ref(), trait_name);
+        // Panicking while already panicking would abort, in which case the panic is left for
+        // take_panic to pick up.
+        let drop_resume = quote! {
+            if !::std::thread::panicking() {
+                #drop_resume
+            }
+        };
+        let slot_name = panic_slot_name_from_trait_name(trait_name);
+        let take_panic = quote! {
+            /// Takes the panic which was caught at the FFI boundary by a method of any object of this trait on the current thread, if there is one which has not been resumed yet.
+            ///
+            /// Such panics are resumed automatically when calling methods through the thin trait object, but they need to be retrieved with this function if the methods are called through the vtable directly, such as by C code.
+            #[inline]
+            pub fn take_panic() -> ::core::option::Option<
+                ::std::boxed::Box<dyn ::core::any::Any + ::core::marker::Send + 'static>
+            > {
+                #slot_name.with(::core::cell::Cell::take)
+            }
+        };
+        (drop_resume, take_panic)
+    } else {
+        Default::default()
+    };
+    // The trait cannot be implemented on the thin trait object if it has a closure supertrait, and
+    // it's covered by the blanket implementation otherwise.
+    let trait_impl = if inherent_thunks.is_empty() && !blanket_impl {
+        quote! {
+            #[allow(clippy::ref_in_deref)] // see https://github.com/rust-lang/rust-clippy/issues/6658
+            impl #trait_name for #trait_object_name #impl_elided_lifetime {
+                #(#impl_thunks)*
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let pinned_trait_object = if has_pinned_receivers {
+        generate_pinned_trait_object(
+            trait_name,
+            trait_object_name,
+            vtable_items,
+            &visibility,
+            &generics,
+            &creation_bound,
+            &impl_elided_lifetime,
+        )
+    } else {
+        quote! {}
+    };
+    let forwarded_impls = generate_forwarded_impls(
+        forwarded_traits,
+        vtable_items,
+        repr_name,
+        quote! {},
+        quote! { #trait_object_name #impl_elided_lifetime },
+        *panic_policy,
+        trait_name,
+    );
+    let stack_objects = if *stack_objects {
+        check_unboxed_objects("stack_objects", trait_name, vtable_items)?;
+        let mut_name = format_ident!("Mut{}", trait_name);
+        // A borrowed object cannot implement a trait which requires `'static`, so the methods are
+        // called through inherent methods instead.
+        let (mut_trait_impl, mut_inherent_thunks) = if !inherent_thunks.is_empty() || blanket_impl {
+            (quote! {}, quote! {})
+        } else if has_static_bound {
+            let mut_inherent_thunks = quote! {
+                #(
+                    /// Calls the method of the trait with the same name.
+                    #[inline]
+                    pub #impl_thunks
+                )*
+            };
+            (quote! {}, mut_inherent_thunks)
+        } else {
+            let mut_trait_impl = quote! {
+                #[allow(clippy::ref_in_deref)]
+                impl #trait_name for #mut_name<'_> {
+                    #(#impl_thunks)*
+                }
+            };
+            (mut_trait_impl, quote! {})
+        };
+        let mut_forwarded_impls = generate_forwarded_impls(
+            forwarded_traits,
+            vtable_items,
+            repr_name,
+            quote! {},
+            quote! { #mut_name<'_> },
+            *panic_policy,
+            trait_name,
+        );
+        let mut_marker_impls = markers
+            .iter()
+            .map(|marker_trait| marker_trait.as_impl_for(&quote! {}, &quote! { #mut_name<'_> }));
+        generate_stack_objects(
+            trait_name,
+            repr_name,
+            vtable_name,
+            &visibility,
+            header.as_ref(),
+            quote! {
+                /// Retrieves the raw vtable of the borrowed trait object.
+                pub fn vtable(&self) -> &#vtable_name {
+                    #vtable_getter_impl
+                }
+                #(#inherent_thunks)*
+                #mut_inherent_thunks
+            },
+            quote! {
+                #mut_trait_impl
+                #mut_forwarded_impls
+                #(#mut_marker_impls)*
+            },
+        )
+    } else {
+        quote! {}
+    };
+    let inline_object = if *inline_storage {
+        check_unboxed_objects("inline_storage", trait_name, vtable_items)?;
+        let (inline_generics, inline_type) = inline_object_generics(trait_name, has_static_bound);
+        let inline_trait_impl = if inherent_thunks.is_empty() && !blanket_impl {
+            quote! {
+                #[allow(clippy::ref_in_deref)]
+                impl #inline_generics #trait_name for #inline_type {
+                    #(#impl_thunks)*
+                }
+            }
+        } else {
+            quote! {}
+        };
+        let inline_forwarded_impls = generate_forwarded_impls(
+            forwarded_traits,
+            vtable_items,
+            repr_name,
+            inline_generics.clone(),
+            inline_type.clone(),
+            *panic_policy,
+            trait_name,
+        );
+        let inline_marker_impls = markers
+            .iter()
+            .map(|marker_trait| marker_trait.as_impl_for(&inline_generics, &inline_type));
+        // The storage has to be able to hold everything that precedes the value in the repr struct.
+        let storage_prefix = if header.is_some() {
+            quote! { #prefix_name }
+        } else if inline_vtable {
+            quote! { #vtable_name }
+        } else {
+            quote! { &'static #vtable_name }
+        };
+        let inline_object = generate_inline_object(
+            trait_name,
+            trait_object_name,
+            repr_name,
+            &visibility,
+            header.as_ref(),
+            storage_prefix,
+            has_static_bound,
+        );
+        quote! {
+            #inline_object
+            impl #inline_generics #inline_type {
+                /// Retrieves the raw vtable of the contained trait object.
+                pub fn vtable(&self) -> &#vtable_name {
+                    #vtable_getter_impl
+                }
+                #(#inherent_thunks)*
+            }
+            #inline_trait_impl
+            #inline_forwarded_impls
+            #(#inline_marker_impls)*
+        }
+    } else {
+        quote! {}
+    };
+    let runtime_impl = runtime.as_ref().map(|runtime| {
+        quote! {
+            unsafe impl #runtime::ThinTraitObject for #trait_object_name #impl_elided_lifetime {
+                type Vtable = #vtable_name;
+                #[inline]
+                unsafe fn from_raw(ptr: *mut ()) -> Self {
+                    #trait_object_name::from_raw(ptr)
+                }
+                #[inline]
+                fn as_raw(&self) -> *mut () {
+                    #trait_object_name::as_raw(self)
+                }
+                #[inline]
+                fn into_raw(self) -> *mut () {
+                    #trait_object_name::into_raw(self)
+                }
+                #[inline]
+                fn vtable(&self) -> &#vtable_name {
+                    #trait_object_name::vtable(self)
+                }
+            }
+        }
+    });
     let result = quote! {
         #(#attributes)*
         #[repr(transparent)]
@@ -119,14 +650,9 @@ pub fn generate_trait_object<'a>(
             #phantomdata,
         );
         impl #generics #trait_object_name #generics {
-            /// Constructs a boxed thin trait object from a type implementing the trait.
-            #[inline]
-            pub fn new<
-                T: #trait_name + Sized + #creation_bound
-                >(val: T) -> Self {
-                    unsafe { Self::from_raw(#repr_name::__thintraitobjectmacro_repr_create(val) as *mut _) }
-            }
+            #constructors
             /// Creates a thin trait object directly from a raw pointer to its vtable.
+            #raw_pointer_doc
             ///
             /// # Safety
             /// This constructor, by its nature, is hugely unsafe and should be avoided when possible. The following invariants must be upheld:
@@ -146,6 +672,7 @@ pub fn generate_trait_object<'a>(
                 Self(::core::ptr::NonNull::new_unchecked(ptr as *mut _), ::core::marker::PhantomData)
             }
             /// Extracts the contained pointer to the trait object.
+            #raw_pointer_doc
             ///
             /// Unlike [`into_raw`], ownership of the pointer is not released, and as such will be dropped normally. Unless the original copy is removed via [`mem::forget`] or [`ManuallyDrop`], calling [`from_raw`] and then dropping will cause undefined behavior.
             ///
@@ -158,6 +685,7 @@ pub fn generate_trait_object<'a>(
                 self.0.as_ptr() as *mut ()
             }
             /// Releases ownership of the trait object, returning the contained pointer. It is the caller's responsibility to drop the trait object at a later time using [`from_raw`].
+            #raw_pointer_doc
             ///
             /// For a version which does not release ownership, see [`as_raw`].
             ///
@@ -173,21 +701,414 @@ pub fn generate_trait_object<'a>(
             pub fn vtable(&self) -> &#vtable_name {
                 #vtable_getter_impl
             }
+            #header_getters
+            #(#callback_getters)*
+            #take_panic
+            #(#inherent_thunks)*
         }
-        #[allow(clippy::ref_in_deref)] // see https://github.com/rust-lang/rust-clippy/issues/6658
-        impl #trait_name for #trait_object_name #impl_elided_lifetime {
-            #(#impl_thunks)*
-        }
+        #default_header_constructors
+        #trait_impl
+        #forwarded_impls
+        #runtime_impl
         impl ::core::ops::Drop for #trait_object_name #impl_elided_lifetime {
             fn drop(&mut self) {
-                unsafe { (self.vtable().drop)(self.0.as_ptr() as *mut ::core::ffi::c_void) }
+                unsafe { (#drop_function)(self.0.as_ptr() as _) }
+                #drop_resume
             }
         }
         #(#marker_impls)*
+        #pinned_trait_object
+        #stack_objects
+        #inline_object
     };
     Ok(result)
 }
 
+/// Generates the `Pinned...` struct, which owns a thin trait object and never hands out mutable references to the value, allowing the methods with pinned receivers to be called through it.
+fn generate_pinned_trait_object(
+    trait_name: &Ident,
+    trait_object_name: &Ident,
+    vtable_items: &[VtableItem],
+    visibility: &Visibility,
+    generics: &TokenStream,
+    creation_bound: &TokenStream,
+    impl_elided_lifetime: &TokenStream,
+) -> TokenStream {
+    let pinned_name = format_ident!("Pinned{}", trait_name);
+    let methods = vtable_items.iter().filter(|item| item.forwarded.is_none()).filter_map(|item| {
+        let pinned_receiver = item.pinned_receiver();
+        let mutability = match (pinned_receiver, item.receiver()) {
+            (Some(mutability), _) => mutability,
+            (None, Some(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_none() =>
+            {
+                None
+            }
+            // Mutable references would allow the value to be moved out.
+            _ => return None,
+        };
+        let mut signature = item.clone().into_signature(|x| format_ident!("__arg{}", x));
+        let args = signature
+            .inputs
+            .iter()
+            .skip(1)
+            .map(|arg| match arg {
+                FnArg::Typed(arg) => arg.pat.to_token_stream(),
+                FnArg::Receiver(..) => unreachable!("only the first argument can be the receiver"),
+            })
+            .collect::<Vec<_>>();
+        *signature.inputs.first_mut().unwrap() = parse_quote!(&#mutability self);
+        let name = &signature.ident;
+        let method = quote! { <#trait_object_name #impl_elided_lifetime as #trait_name>::#name };
+        let body = if pinned_receiver.is_some() {
+            quote! {
+                unsafe { #method(::core::pin::Pin::new_unchecked(&#mutability self.0), #(#args),*) }
+            }
+        } else {
+            quote! { #method(&self.0, #(#args),*) }
+        };
+        Some(quote! {
+            #[inline]
+            pub #signature {
+                #body
+            }
+        })
+    });
+    let doc = format!(
+        "\
+A boxed thin trait object which keeps the value pinned, allowing the methods of `{}` which take \
+`self: Pin<&Self>` or `self: Pin<&mut Self>` to be called on it.
+
+Methods which take `&mut self` are not available, since those could move the value out of its \
+place.",
+        trait_name,
+    );
+    quote! {
+        #[doc = #doc]
+        #[repr(transparent)]
+        #visibility struct #pinned_name #generics (#trait_object_name #generics);
+        impl #generics #pinned_name #generics {
+            /// Constructs a pinned thin trait object from a type implementing the trait.
+            #[inline]
+            pub fn new<
+                T: #trait_name + Sized + #creation_bound
+                >(val: T) -> Self {
+                    Self(#trait_object_name::new(val))
+            }
+            #(#methods)*
+        }
+        impl #generics ::core::convert::From<#trait_object_name #generics> for #pinned_name #generics {
+            /// Pins the value contained in a thin trait object, which does not move it.
+            #[inline]
+            fn from(object: #trait_object_name #generics) -> Self {
+                Self(object)
+            }
+        }
+        // The value is never moved out of its allocation, regardless of what happens to this struct.
+        impl #generics ::core::marker::Unpin for #pinned_name #generics {}
+    }
+}
+
+/// Checks whether the methods of the trait can be called on a value which is not stored in an allocation of its own, which is what `option` does.
+fn check_unboxed_objects(
+    option: &str,
+    trait_name: &Ident,
+    vtable_items: &[VtableItem],
+) -> syn::Result<()> {
+    for item in vtable_items {
+        if item.closure == Some(ClosureKind::FnOnce) {
+            return Err(syn::Error::new_spanned(
+                trait_name,
+                format!(
+                    "`{}` cannot be used with `FnOnce` thin closures, since calling them frees the \
+object",
+                    option,
+                ),
+            ));
+        }
+        if item.pinned_receiver().is_some() {
+            return Err(syn::Error::new_spanned(
+                &item.name,
+                format!(
+                    "`{}` cannot be used with methods which take pinned receivers, since the value \
+is moved along with the object",
+                    option,
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Generates the `Stack...` struct, which stores the value along with the vtable pointer without allocating, and the `Mut...` struct, which is a thin trait object borrowed from it.
+fn generate_stack_objects(
+    trait_name: &Ident,
+    repr_name: &Ident,
+    vtable_name: &Ident,
+    visibility: &Visibility,
+    header: Option<&Type>,
+    mut_methods: TokenStream,
+    mut_impls: TokenStream,
+) -> TokenStream {
+    let stack_name = format_ident!("Stack{}", trait_name);
+    let mut_name = format_ident!("Mut{}", trait_name);
+    let (constructors, default_header_constructors) = match header {
+        Some(header) => (
+            quote! {
+                /// Constructs a stack object from a type implementing the trait and the header which is to precede the vtable pointer.
+                #[inline]
+                pub fn new_with_header(header: #header, val: T) -> Self {
+                    Self(#repr_name::__thintraitobjectmacro_repr_new(val, header))
+                }
+            },
+            // Bounded the same way as the constructors of the thin trait object.
+            quote! {
+                impl<T: #trait_name> #stack_name<T>
+                where
+                    for<'__thintraitobjectmacro_header> #header: ::core::default::Default,
+                {
+                    /// Constructs a stack object from a type implementing the trait, using the default value for the header.
+                    #[inline]
+                    pub fn new(val: T) -> Self {
+                        Self::new_with_header(::core::default::Default::default(), val)
+                    }
+                }
+            },
+        ),
+        None => (
+            quote! {
+                /// Constructs a stack object from a type implementing the trait.
+                #[inline]
+                pub fn new(val: T) -> Self {
+                    Self(#repr_name::__thintraitobjectmacro_repr_new(val))
+                }
+            },
+            quote! {},
+        ),
+    };
+    let stack_doc = format!(
+        "\
+A value implementing `{}` stored together with the vtable pointer, in the same layout as the \
+allocation of a thin trait object, but wherever the struct itself is placed, such as on the stack.
+
+Use `as_thin` to borrow it as a [`{}`], which is one pointer wide.",
+        trait_name, mut_name,
+    );
+    let mut_doc = format!(
+        "\
+A thin trait object which borrows the value and the vtable pointer instead of owning them, \
+created from a [`{}`] or from a raw pointer. Never frees the object.",
+        stack_name,
+    );
+    quote! {
+        #[doc = #stack_doc]
+        #visibility struct #stack_name<T: #trait_name>(#repr_name<T>);
+        impl<T: #trait_name> #stack_name<T> {
+            #constructors
+            /// Borrows the stack object as a thin trait object, which is valid for as long as the stack object stays borrowed.
+            #[inline]
+            pub fn as_thin(&mut self) -> #mut_name<'_> {
+                unsafe { #mut_name::from_raw(&mut self.0 as *mut #repr_name<T> as *mut ()) }
+            }
+            /// Moves the value out of the stack object.
+            #[inline]
+            pub fn into_inner(self) -> T {
+                self.0.__thintraitobjectmacro_repr_value
+            }
+        }
+        #default_header_constructors
+        #[doc = #mut_doc]
+        #[repr(transparent)]
+        #visibility struct #mut_name<'a>(
+            ::core::ptr::NonNull<#vtable_name>,
+            ::core::marker::PhantomData<&'a mut ()>,
+        );
+        impl<'a> #mut_name<'a> {
+            /// Creates a borrowed thin trait object directly from a raw pointer to its vtable.
+            ///
+            /// # Safety
+            /// The pointer must not be null and must point to a valid thin trait object as expected by its vtable, which must not be used through any other pointer or dropped for the lifetime `'a`. The same invariants as for the `from_raw` constructor of the owned thin trait object apply to the vtable.
+            #[inline]
+            pub const unsafe fn from_raw(ptr: *mut ()) -> Self {
+                Self(::core::ptr::NonNull::new_unchecked(ptr as *mut _), ::core::marker::PhantomData)
+            }
+            /// Extracts the contained pointer to the trait object, which stays owned by the stack object or whoever created the borrowed one.
+            #[inline]
+            pub const fn as_raw(&self) -> *mut () {
+                self.0.as_ptr() as *mut ()
+            }
+            #mut_methods
+        }
+        #mut_impls
+    }
+}
+
+/// Returns the generic parameters of the `Inline...` struct and the struct with those applied.
+fn inline_object_generics(
+    trait_name: &Ident,
+    has_static_bound: bool,
+) -> (TokenStream, TokenStream) {
+    let inline_name = format_ident!("Inline{}", trait_name);
+    if has_static_bound {
+        (quote! { <const N: usize> }, quote! { #inline_name<N> })
+    } else {
+        (
+            quote! { <'inner, const N: usize> },
+            quote! { #inline_name<'inner, N> },
+        )
+    }
+}
+
+/// Generates the `Inline...` struct, which stores values of up to `N` bytes along with the vtable pointer inside of itself and boxes larger ones.
+fn generate_inline_object(
+    trait_name: &Ident,
+    trait_object_name: &Ident,
+    repr_name: &Ident,
+    visibility: &Visibility,
+    header: Option<&Type>,
+    storage_prefix: TokenStream,
+    has_static_bound: bool,
+) -> TokenStream {
+    let (generics, inline_type) = inline_object_generics(trait_name, has_static_bound);
+    let inline_name = format_ident!("Inline{}", trait_name);
+    let storage_name = format_ident!("__ThinTraitObjectMacro_InlineStorageFor{}", trait_name);
+    let (boxed_type, creation_bound) = if has_static_bound {
+        (quote! { #trait_object_name }, quote! {})
+    } else {
+        (quote! { #trait_object_name<'inner> }, quote! { 'inner })
+    };
+    let (constructors, default_header_constructors, header_arg, boxed_new) = match header {
+        Some(header) => (
+            quote! {
+                /// Constructs an inline object from a type implementing the trait and the header which is to precede the vtable pointer.
+                pub fn new_with_header<
+                    T: #trait_name + Sized + #creation_bound
+                    >(header: #header, val: T) -> Self
+            },
+            // Bounded the same way as the constructors of the thin trait object.
+            quote! {
+                impl #generics #inline_type
+                where
+                    for<'__thintraitobjectmacro_header> #header: ::core::default::Default,
+                {
+                    /// Constructs an inline object from a type implementing the trait, using the default value for the header.
+                    #[inline]
+                    pub fn new<T: #trait_name + Sized + #creation_bound>(val: T) -> Self {
+                        Self::new_with_header(::core::default::Default::default(), val)
+                    }
+                }
+            },
+            quote! { , header },
+            quote! { #trait_object_name::new_with_header(header, val) },
+        ),
+        None => (
+            quote! {
+                /// Constructs an inline object from a type implementing the trait.
+                pub fn new<T: #trait_name + Sized + #creation_bound>(val: T) -> Self
+            },
+            quote! {},
+            quote! {},
+            quote! { #trait_object_name::new(val) },
+        ),
+    };
+    let doc = format!(
+        "\
+A thin trait object for `{}` which stores values of up to `N` bytes inside of itself along with \
+the vtable pointer, in the same layout as the allocation of a [`{}`], and boxes larger ones.
+
+Values which are aligned more strictly than the vtable pointer are always boxed.",
+        trait_name, trait_object_name,
+    );
+    quote! {
+        #[repr(C)]
+        struct #storage_name<const N: usize> {
+            _prefix: ::core::mem::MaybeUninit<#storage_prefix>,
+            _value: ::core::mem::MaybeUninit<[u8; N]>,
+        }
+        #[doc = #doc]
+        #visibility struct #inline_name #generics {
+            // Holds either the repr struct or the boxed thin trait object.
+            storage: ::core::cell::UnsafeCell<#storage_name<N>>,
+            // The destructor of the repr struct if it's stored inline.
+            drop_inline: ::core::option::Option<unsafe fn(*mut ())>,
+            _phantom: ::core::marker::PhantomData<#boxed_type>,
+        }
+        impl #generics #inline_type {
+            #constructors {
+                let mut storage = #storage_name::<N> {
+                    _prefix: ::core::mem::MaybeUninit::uninit(),
+                    _value: ::core::mem::MaybeUninit::uninit(),
+                };
+                let fits = ::core::mem::size_of::<#repr_name<T>>()
+                    <= ::core::mem::size_of::<#storage_name<N>>()
+                    && ::core::mem::align_of::<#repr_name<T>>()
+                        <= ::core::mem::align_of::<#storage_name<N>>();
+                let drop_inline = if fits {
+                    unsafe {
+                        (&mut storage as *mut #storage_name<N> as *mut #repr_name<T>)
+                            .write(#repr_name::__thintraitobjectmacro_repr_new(val #header_arg));
+                    }
+                    ::core::option::Option::Some(
+                        #repr_name::<T>::__thintraitobjectmacro_repr_drop_in_place
+                            as unsafe fn(*mut ())
+                    )
+                } else {
+                    unsafe {
+                        (&mut storage as *mut #storage_name<N> as *mut #boxed_type)
+                            .write(#boxed_new);
+                    }
+                    ::core::option::Option::None
+                };
+                Self {
+                    storage: ::core::cell::UnsafeCell::new(storage),
+                    drop_inline,
+                    _phantom: ::core::marker::PhantomData,
+                }
+            }
+            /// Returns `true` if the value is stored inside the object, or `false` if it's boxed.
+            #[inline]
+            pub fn is_inline(&self) -> bool {
+                self.drop_inline.is_some()
+            }
+            /// Extracts the pointer to the trait object, which points either inside the inline object or to the allocation of the boxed value, and stays valid until the inline object is moved or dropped.
+            #[inline]
+            pub fn as_raw(&self) -> *mut () {
+                if self.is_inline() {
+                    self.storage.get() as *mut ()
+                } else {
+                    unsafe { (*(self.storage.get() as *const #boxed_type)).as_raw() }
+                }
+            }
+        }
+        #default_header_constructors
+        impl #generics ::core::ops::Drop for #inline_type {
+            fn drop(&mut self) {
+                unsafe {
+                    match self.drop_inline {
+                        ::core::option::Option::Some(drop_inline) => {
+                            drop_inline(self.storage.get() as *mut ())
+                        }
+                        ::core::option::Option::None => {
+                            ::core::ptr::drop_in_place(self.storage.get() as *mut #boxed_type)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Panics with a descriptive message if a nullable vtable entry turns out to be null.
+fn unwrap_nullable(function: TokenStream, nullable: bool) -> TokenStream {
+    if nullable {
+        quote! {
+            #function.expect("attempt to call a null function pointer in a thin trait object vtable")
+        }
+    } else {
+        function
+    }
+}
+
 fn check_attribute(attribute: &Attribute) -> syn::Result<()> {
     let name = &attribute.path;
     let ident = &name.segments[0].ident;
@@ -0,0 +1,173 @@
+//! Handling of panics which reach the FFI boundary.
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Abi, LitStr};
+
+/// What the thunks do when the implementation of a method panics.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Abort the process.
+    Abort,
+    /// Return `Default::default()` from the thunk, discarding the panic.
+    CatchReturnDefault,
+    /// Return `Default::default()` from the thunk, storing the panic in a thread-local so that the thin trait object could resume it on the Rust side.
+    CatchAndStore,
+}
+impl PanicPolicy {
+    pub fn from_lit(lit: &LitStr) -> syn::Result<Self> {
+        match lit.value().as_str() {
+            "abort" => Ok(Self::Abort),
+            "catch_return_default" => Ok(Self::CatchReturnDefault),
+            "catch_and_store" => Ok(Self::CatchAndStore),
+            _ => Err(syn::Error::new_spanned(
+                lit,
+                "expected \"abort\", \"catch_return_default\" or \"catch_and_store\"",
+            )),
+        }
+    }
+}
+
+/// Whether a panic can unwind out of a function with the specified ABI, which is only the case for the Rust ABI and foreign ABIs with the `-unwind` suffix.
+pub fn can_unwind(abi: Option<&Abi>) -> bool {
+    match abi {
+        Some(abi) => match &abi.name {
+            Some(name) => {
+                let name = name.value();
+                name == "Rust" || name.ends_with("-unwind")
+            }
+            // `extern fn` is `extern "C" fn`.
+            None => false,
+        },
+        None => true,
+    }
+}
+/// Whether a panic in a method with the first ABI has to be caught by a thunk with the second ABI according to the panic policy. When the method itself cannot unwind, the panic aborts before the thunk can do anything about it.
+pub fn needs_catching(method_abi: Option<&Abi>, slot_abi: Option<&Abi>) -> bool {
+    can_unwind(method_abi) && !can_unwind(slot_abi)
+}
+/// Returns the ABI with the `-unwind` suffix removed, if it had one.
+pub fn without_unwind(abi: &Abi) -> Option<Abi> {
+    let name = abi.name.as_ref()?;
+    let value = name.value();
+    let stripped = value.strip_suffix("-unwind")?;
+    Some(Abi {
+        extern_token: abi.extern_token,
+        name: Some(LitStr::new(stripped, name.span())),
+    })
+}
+/// Returns the ABI with the `-unwind` suffix added, unless it already had one or is the Rust ABI.
+pub fn with_unwind(abi: &Abi) -> Option<Abi> {
+    if can_unwind(Some(abi)) {
+        return None;
+    }
+    let (value, span) = match &abi.name {
+        Some(name) => (name.value(), name.span()),
+        None => ("C".to_string(), abi.extern_token.span),
+    };
+    Some(Abi {
+        extern_token: abi.extern_token,
+        name: Some(LitStr::new(&format!("{}-unwind", value), span)),
+    })
+}
+
+#[inline]
+pub fn panic_slot_name_from_trait_name(trait_name: &Ident) -> Ident {
+    format_ident!("__THINTRAITOBJECTMACRO_PANIC_FOR_{}", trait_name)
+}
+
+/// Generates the thread-local which stores caught panics, if the policy needs one.
+pub fn generate_panic_slot(policy: Option<PanicPolicy>, trait_name: &Ident) -> TokenStream {
+    if policy != Some(PanicPolicy::CatchAndStore) {
+        return quote! {};
+    }
+    let slot_name = panic_slot_name_from_trait_name(trait_name);
+    quote! {
+        ::std::thread_local! {
+            #[allow(non_upper_case_globals)]
+            static #slot_name: ::core::cell::Cell<
+                ::core::option::Option<
+                    ::std::boxed::Box<dyn ::core::any::Any + ::core::marker::Send + 'static>
+                >
+            > = ::core::cell::Cell::new(::core::option::Option::None);
+        }
+    }
+}
+
+/// Wraps the body of a thunk into `catch_unwind` and applies the policy to the caught panic. If the policy discards the panic, the thunk returns `panic_return`, or `Default::default()` if it's `None`. Without a policy, the body is wrapped into a guard which aborts the process if a panic unwinds out of it.
+pub fn wrap_thunk_body(
+    policy: Option<PanicPolicy>,
+    method_abi: Option<&Abi>,
+    slot_abi: Option<&Abi>,
+    trait_name: &Ident,
+    body: TokenStream,
+    panic_return: Option<TokenStream>,
+) -> TokenStream {
+    let policy = match policy {
+        _ if !needs_catching(method_abi, slot_abi) => return body,
+        Some(policy) => policy,
+        None => return abort_on_unwind(body),
+    };
+    let panic_return =
+        panic_return.unwrap_or_else(|| quote! { ::core::default::Default::default() });
+    let on_panic = match policy {
+        PanicPolicy::Abort => quote! {
+            ::core::mem::drop(__thintraitobjectmacro_payload);
+            ::std::process::abort()
+        },
+        PanicPolicy::CatchReturnDefault => quote! {
+            ::core::mem::drop(__thintraitobjectmacro_payload);
+            #panic_return
+        },
+        PanicPolicy::CatchAndStore => {
+            let slot_name = panic_slot_name_from_trait_name(trait_name);
+            quote! {
+                #slot_name.with(|slot| slot.set(
+                    ::core::option::Option::Some(__thintraitobjectmacro_payload)
+                ));
+                #panic_return
+            }
+        }
+    };
+    quote! {
+        match ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(move || { #body })) {
+            ::core::result::Result::Ok(__thintraitobjectmacro_ret) => __thintraitobjectmacro_ret,
+            ::core::result::Result::Err(__thintraitobjectmacro_payload) => { #on_panic }
+        }
+    }
+}
+
+/// Wraps the body of a thunk into a guard which panics when dropped during unwinding, which makes the process abort. Unwinding out of a function with a foreign ABI is undefined behavior before Rust 1.81, so the thunk has to abort by itself, and this works without `std`.
+fn abort_on_unwind(body: TokenStream) -> TokenStream {
+    quote! {{
+        struct AbortOnUnwind;
+        impl ::core::ops::Drop for AbortOnUnwind {
+            fn drop(&mut self) {
+                panic!("a panic cannot unwind out of a vtable entry with a foreign ABI");
+            }
+        }
+        let guard = AbortOnUnwind;
+        // The closure keeps `return` in the body from skipping the rest.
+        let ret = (move || { #body })();
+        ::core::mem::forget(guard);
+        ret
+    }}
+}
+
+/// Generates a statement which resumes the panic stored by a thunk, if there is one. Used by the thin trait object after calling into the vtable.
+pub fn resume_stored_panic(
+    policy: Option<PanicPolicy>,
+    method_abi: Option<&Abi>,
+    slot_abi: Option<&Abi>,
+    trait_name: &Ident,
+) -> TokenStream {
+    if policy != Some(PanicPolicy::CatchAndStore) || !needs_catching(method_abi, slot_abi) {
+        return quote! {};
+    }
+    let slot_name = panic_slot_name_from_trait_name(trait_name);
+    quote! {
+        if let ::core::option::Option::Some(payload) = #slot_name.with(::core::cell::Cell::take) {
+            ::std::panic::resume_unwind(payload);
+        }
+    }
+}
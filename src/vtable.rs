@@ -1,8 +1,14 @@
 //! Generates the vtable struct itself.
 
-use crate::attr::StageStash;
+use crate::{
+    attr::StageStash,
+    closure::ClosureKind,
+    forward::ForwardedMethod,
+    lowering::LoweredResult,
+    marshal::Marshal,
+};
 use proc_macro2::{Ident, Span, TokenStream};
-use quote::{quote, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use replace_with::replace_with_or_abort;
 use std::convert::TryFrom;
 use syn::{
@@ -15,6 +21,7 @@ use syn::{
     BareFnArg,
     BoundLifetimes,
     FnArg,
+    GenericArgument,
     GenericParam,
     Generics,
     LitStr,
@@ -41,12 +48,12 @@ pub fn generate_vtable(
     stash: &mut StageStash,
     visibility: Visibility,
     attributes: impl IntoIterator<Item = Attribute>,
-    drop_abi: Option<&Abi>,
     store_layout: bool,
 ) -> TokenStream {
     let StageStash {
         vtable_items: items,
         vtable_name: name,
+        drop_slot,
         ..
     } = stash;
     let all_attributes = {
@@ -64,23 +71,18 @@ pub fn generate_vtable(
         token_stream
     };
     struct VtableItemToFnPtr(VtableItem);
-    impl<'a> ToTokens for VtableItemToFnPtr {
+    impl ToTokens for VtableItemToFnPtr {
         fn to_tokens(&self, out: &mut TokenStream) {
             out.extend(self.to_token_stream());
         }
         fn to_token_stream(&self) -> TokenStream {
             let name = self.0.name.clone();
-            let ty = {
-                let mut owned = self.0.clone();
-                owned.make_unsafe();
-                owned.make_raw();
-                owned.to_function_pointer()
-            };
+            let ty = self.0.to_raw_function_pointer();
             quote! { #name : #ty }
         }
     }
     struct VtableItemToDebugImplLine(VtableItem);
-    impl<'a> ToTokens for VtableItemToDebugImplLine {
+    impl ToTokens for VtableItemToDebugImplLine {
         fn to_tokens(&self, out: &mut TokenStream) {
             out.extend(self.to_token_stream());
         }
@@ -91,7 +93,7 @@ pub fn generate_vtable(
         }
     }
     struct VtableItemToHashImplLine(VtableItem);
-    impl<'a> ToTokens for VtableItemToHashImplLine {
+    impl ToTokens for VtableItemToHashImplLine {
         fn to_tokens(&self, out: &mut TokenStream) {
             out.extend(self.to_token_stream());
         }
@@ -104,6 +106,7 @@ pub fn generate_vtable(
     let debug_impl_lines = items.iter().cloned().map(VtableItemToDebugImplLine);
     let hash_impl_lines = items.iter().cloned().map(VtableItemToHashImplLine);
     let name_strlit = LitStr::new(&name.to_string(), Span::call_site());
+    let drop_type = drop_slot.to_function_pointer();
     let size_and_align = if store_layout {
         quote! {
             pub size: usize,
@@ -118,7 +121,7 @@ pub fn generate_vtable(
         #visibility struct #name {
             #size_and_align
             #(pub #vtable_entries,)*
-            pub drop: unsafe #drop_abi fn(*mut ::core::ffi::c_void),
+            pub drop: #drop_type,
         }
         impl ::core::fmt::Debug for #name {
             fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
@@ -135,6 +138,50 @@ pub fn generate_vtable(
     }
 }
 
+/// Generates declarations of foreign functions with the same signatures as the vtable entries which have a foreign ABI, with the `improper_ctypes` lint denied for them. This makes the compiler point at every type which C code cannot use.
+pub fn generate_ffi_checks(stash: &StageStash) -> TokenStream {
+    let StageStash {
+        vtable_items,
+        drop_slot,
+        ..
+    } = stash;
+    let mut checks = TokenStream::new();
+    for item in vtable_items.iter().filter(|item| item.has_foreign_abi()) {
+        let mut raw = item.clone();
+        raw.make_raw();
+        let name = format_ident!("__thintraitobjectmacro_ffi_check_{}", raw.name);
+        let abi = &raw.abi;
+        let generics = lifetimes_to_generics(raw.lifetimes);
+        let inputs = raw
+            .inputs
+            .into_iter()
+            .map(|arg| arg.into_bare_arg_with_ptr_receiver(None).ty);
+        let output = &raw.output;
+        checks.extend(quote! {
+            #abi {
+                fn #name #generics (#(_: #inputs),*) #output;
+            }
+        });
+    }
+    if is_foreign_abi(drop_slot.abi.as_ref()) {
+        let abi = &drop_slot.abi;
+        let receiver = raw_receiver_type(drop_slot.raw_receiver.as_ref());
+        checks.extend(quote! {
+            #abi {
+                fn __thintraitobjectmacro_ffi_check_drop(_: #receiver);
+            }
+        });
+    }
+    // Never called, the declarations are only there to be linted.
+    quote! {
+        #[deny(improper_ctypes)]
+        #[allow(dead_code)]
+        const _: () = {
+            #checks
+        };
+    }
+}
+
 fn repr_attribute() -> Attribute {
     let path = {
         let mut segments = Punctuated::new();
@@ -164,36 +211,50 @@ fn repr_attribute() -> Attribute {
 }
 
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum VtableFnArg {
     Normal(BareFnArg),
     Receiver(Receiver),
+    /// `self: Pin<&Self>` or `self: Pin<&mut Self>`, along with the mutability of the reference.
+    PinnedReceiver(PatType, Option<Token![mut]>),
 }
 impl VtableFnArg {
-    pub fn into_bare_arg_with_ptr_receiver(self) -> BareFnArg {
+    /// Replaces the receiver, if that's what this is, with `*mut c_void` or with the specified raw receiver type.
+    pub fn into_bare_arg_with_ptr_receiver(self, raw_receiver: Option<&Type>) -> BareFnArg {
         match self {
             VtableFnArg::Normal(arg) => arg,
-            VtableFnArg::Receiver(arg) => BareFnArg {
-                attrs: arg.attrs,
+            VtableFnArg::Receiver(Receiver { attrs, .. })
+            | VtableFnArg::PinnedReceiver(PatType { attrs, .. }, _) => BareFnArg {
+                attrs,
                 name: None, // Fill this out later.
-                ty: Type::Ptr(TypePtr {
-                    star_token: Default::default(),
-                    const_token: None,
-                    mutability: Some(Default::default()),
-                    elem: Type::Path(TypePath {
-                        qself: None,
-                        path: define_path![::, "core", "ffi", "c_void"],
-                    })
-                    .into(),
-                }),
+                ty: raw_receiver_type(raw_receiver),
             },
         }
     }
 }
+
+/// The type which the receiver is passed as to a vtable entry: the raw receiver type if one is specified, `*mut c_void` otherwise.
+pub fn raw_receiver_type(raw_receiver: Option<&Type>) -> Type {
+    raw_receiver.cloned().unwrap_or_else(|| {
+        Type::Ptr(TypePtr {
+            star_token: Default::default(),
+            const_token: None,
+            mutability: Some(Default::default()),
+            elem: Type::Path(TypePath {
+                qself: None,
+                path: define_path![::, "core", "ffi", "c_void"],
+            })
+            .into(),
+        })
+    })
+}
+
 impl ToTokens for VtableFnArg {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         match self {
             VtableFnArg::Normal(x) => x.to_tokens(tokens),
             VtableFnArg::Receiver(x) => x.to_tokens(tokens),
+            VtableFnArg::PinnedReceiver(x, _) => x.to_tokens(tokens),
         }
     }
 }
@@ -201,6 +262,18 @@ impl TryFrom<FnArg> for VtableFnArg {
     type Error = syn::Error;
     fn try_from(value: FnArg) -> Result<Self, Self::Error> {
         let success = match value {
+            FnArg::Typed(ty) if matches!(&*ty.pat, Pat::Ident(x) if x.ident == "self") => {
+                match pinned_receiver_mutability(&ty.ty) {
+                    Some(mutability) => Self::PinnedReceiver(ty, mutability),
+                    None => {
+                        return Err(syn::Error::new_spanned(
+                            ty.ty,
+                            "\
+`#[thin_trait_object]` only supports `self: Pin<&Self>` and `self: Pin<&mut Self>` as typed receivers",
+                        ))
+                    }
+                }
+            }
             FnArg::Typed(ty) => Self::Normal(BareFnArg {
                 attrs: ty.attrs,
                 name: match *ty.pat {
@@ -223,6 +296,26 @@ impl TryFrom<FnArg> for VtableFnArg {
         Ok(success)
     }
 }
+/// Recognizes `Pin<&Self>` and `Pin<&mut Self>`, with or without a path leading to `Pin`, returning the mutability of the reference.
+fn pinned_receiver_mutability(ty: &Type) -> Option<Option<Token![mut]>> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    let arguments = match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if segment.ident == "Pin" => &arguments.args,
+        _ => return None,
+    };
+    match (arguments.len(), arguments.first()) {
+        (1, Some(GenericArgument::Type(Type::Reference(reference)))) => match &*reference.elem {
+            Type::Path(path) if path.qself.is_none() && path.path.is_ident("Self") => {
+                Some(reference.mutability)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
 impl From<BareFnArg> for VtableFnArg {
     #[inline]
     fn from(arg: BareFnArg) -> Self {
@@ -236,6 +329,35 @@ impl From<Receiver> for VtableFnArg {
     }
 }
 
+/// The vtable entry which drops the object.
+#[derive(Clone)]
+pub struct DropSlot {
+    pub name: Ident,
+    pub abi: Option<Abi>,
+    /// The type of the only argument, `*mut c_void` if `None`.
+    pub raw_receiver: Option<Type>,
+    /// Whether the vtable stores the function pointer wrapped in an `Option`.
+    pub nullable: bool,
+}
+impl DropSlot {
+    /// The type of the function pointer, not including the `Option` wrapper if it's nullable.
+    pub fn to_function_pointer(&self) -> TokenStream {
+        let abi = &self.abi;
+        let receiver = raw_receiver_type(self.raw_receiver.as_ref());
+        quote! { unsafe #abi fn(#receiver) }
+    }
+}
+impl Default for DropSlot {
+    fn default() -> Self {
+        Self {
+            name: Ident::new("drop", Span::call_site()),
+            abi: None,
+            raw_receiver: None,
+            nullable: false,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct VtableItem {
     pub lifetimes: BoundLifetimes,
@@ -245,6 +367,22 @@ pub struct VtableItem {
     pub inputs: Punctuated<VtableFnArg, Token![,]>,
     pub variadic: Option<Variadic>,
     pub output: ReturnType,
+    /// The type which the receiver takes in the vtable, `*mut c_void` if `None`.
+    pub raw_receiver: Option<Type>,
+    /// Whether the vtable stores the function pointer wrapped in an `Option`.
+    pub nullable: bool,
+    /// The ABI of the vtable entry, if it differs from the ABI of the method.
+    pub slot_abi: Option<Abi>,
+    /// Set if the `Result` returned by the method is lowered to an error code in the vtable.
+    pub lowered_result: Option<LoweredResult>,
+    /// How each of the inputs is marshalled, if at all. Empty if none are.
+    pub marshalled_args: Vec<Option<Marshal>>,
+    /// How the return value is marshalled, if at all.
+    pub marshalled_output: Option<Marshal>,
+    /// Set if the entry calls the closure which the object is, rather than a method of the trait.
+    pub closure: Option<ClosureKind>,
+    /// Set if the entry calls a method of a supertrait which the thin trait object implements too.
+    pub forwarded: Option<ForwardedMethod>,
 }
 impl VtableItem {
     #[inline]
@@ -253,6 +391,35 @@ impl VtableItem {
             self.unsafety = Some(Default::default())
         }
     }
+    /// The ABI of the vtable entry.
+    pub fn effective_slot_abi(&self) -> Option<&Abi> {
+        self.slot_abi.as_ref().or(self.abi.as_ref())
+    }
+    /// Whether the function pointer can be called by foreign code, i.e. has an ABI other than `"Rust"`.
+    pub fn has_foreign_abi(&self) -> bool {
+        is_foreign_abi(self.effective_slot_abi())
+    }
+    /// Returns the receiver of the method, if there is one.
+    pub fn receiver(&self) -> Option<&Receiver> {
+        self.inputs.iter().find_map(|x| match x {
+            VtableFnArg::Receiver(receiver) => Some(receiver),
+            VtableFnArg::Normal(..) | VtableFnArg::PinnedReceiver(..) => None,
+        })
+    }
+    /// Returns the mutability of the reference in the receiver if it's `self: Pin<&Self>` or `self: Pin<&mut Self>`.
+    pub fn pinned_receiver(&self) -> Option<Option<Token![mut]>> {
+        self.inputs.iter().find_map(|x| match x {
+            VtableFnArg::PinnedReceiver(_, mutability) => Some(*mutability),
+            VtableFnArg::Normal(..) | VtableFnArg::Receiver(..) => None,
+        })
+    }
+    /// The type of the function pointer stored in the vtable, not including the `Option` wrapper if it's nullable.
+    pub fn to_raw_function_pointer(&self) -> TokenStream {
+        let mut owned = self.clone();
+        owned.make_unsafe();
+        owned.make_raw();
+        owned.to_function_pointer()
+    }
     pub fn to_function_pointer(&self) -> TokenStream {
         let inputs = self.inputs.iter();
         let lifetimes = &self.lifetimes;
@@ -288,22 +455,56 @@ impl VtableItem {
                         bare_fn_arg_to_fn_arg(arg, &mut inner_default_argname)
                     }
                     VtableFnArg::Receiver(rec) => FnArg::Receiver(rec),
+                    VtableFnArg::PinnedReceiver(rec, _) => FnArg::Typed(rec),
                 })
                 .collect(),
             variadic: self.variadic,
             output: self.output,
         }
     }
+    /// How the input at the specified position is marshalled, if at all.
+    pub fn marshalled_arg(&self, index: usize) -> Option<&Marshal> {
+        self.marshalled_args.get(index).and_then(Option::as_ref)
+    }
     pub fn make_raw(&mut self) -> bool {
+        if let Some(slot_abi) = self.slot_abi.take() {
+            self.abi = Some(slot_abi);
+        }
+        if !self.marshalled_args.is_empty() {
+            let inputs = std::mem::take(&mut self.inputs);
+            for (index, input) in inputs.into_iter().enumerate() {
+                match (self.marshalled_arg(index), input) {
+                    (Some(marshal), VtableFnArg::Normal(arg)) => {
+                        let name = arg.name.as_ref().map(|(name, _)| name);
+                        let raw_args = marshal.raw_args(&arg.attrs, name);
+                        self.inputs
+                            .extend(raw_args.into_iter().map(VtableFnArg::from));
+                    }
+                    (_, input) => self.inputs.push(input),
+                }
+            }
+        }
+        if let Some(marshal) = &self.marshalled_output {
+            self.output = ReturnType::Type(Default::default(), Box::new(marshal.raw_owned_type()));
+        }
         let mut replaced = false;
+        let raw_receiver = self.raw_receiver.as_ref();
         for input in &mut self.inputs {
             replace_with_or_abort(input, |x| {
-                if matches!(x, VtableFnArg::Receiver(..)) {
+                if matches!(
+                    x,
+                    VtableFnArg::Receiver(..) | VtableFnArg::PinnedReceiver(..)
+                ) {
                     replaced = true;
                 }
-                x.into_bare_arg_with_ptr_receiver().into()
+                x.into_bare_arg_with_ptr_receiver(raw_receiver).into()
             });
         }
+        if let Some(lowered_result) = &self.lowered_result {
+            self.inputs
+                .extend(lowered_result.out_arg().map(VtableFnArg::from));
+            self.output = LoweredResult::raw_output();
+        }
         replaced
     }
 }
@@ -337,9 +538,24 @@ impl TryFrom<TraitItemMethod> for VtableItem {
                 .collect::<Result<_, _>>()?,
             variadic: signature.variadic,
             output: signature.output,
+            raw_receiver: None,
+            nullable: false,
+            slot_abi: None,
+            lowered_result: None,
+            marshalled_args: Vec::new(),
+            marshalled_output: None,
+            closure: None,
+            forwarded: None,
         })
     }
 }
+/// Whether the ABI is one other than `"Rust"`. `None` stands for the absence of an `extern` qualifier.
+pub fn is_foreign_abi(abi: Option<&Abi>) -> bool {
+    match abi {
+        Some(abi) => abi.name.as_ref().map(|x| x.value() != "Rust") != Some(false),
+        None => false,
+    }
+}
 fn bare_fn_arg_to_fn_arg(argument: BareFnArg, default_argname: impl FnOnce() -> Ident) -> FnArg {
     let pat = {
         let pat = PatIdent {
@@ -364,7 +580,7 @@ fn bare_fn_arg_to_fn_arg(argument: BareFnArg, default_argname: impl FnOnce() ->
     })
 }
 /// Checks through the generics of the function to see if it has any non-lifetime generics — if it doesn't, convert the generics to an HRTB for a function pointer, if it does, return an error stating that generics are not allowed in function pointers.
-fn generics_to_lifetimes(generics: Generics) -> Result<BoundLifetimes, syn::Error> {
+pub fn generics_to_lifetimes(generics: Generics) -> Result<BoundLifetimes, syn::Error> {
     if let Some(where_clause) = generics.where_clause {
         return Err(syn::Error::new_spanned(
             where_clause,
@@ -394,9 +610,9 @@ fn generics_to_lifetimes(generics: Generics) -> Result<BoundLifetimes, syn::Erro
     };
     Ok(BoundLifetimes {
         for_token: Default::default(),
-        lt_token: generics.lt_token.unwrap_or_else(Default::default),
+        lt_token: generics.lt_token.unwrap_or_default(),
         lifetimes,
-        gt_token: generics.gt_token.unwrap_or_else(Default::default),
+        gt_token: generics.gt_token.unwrap_or_default(),
     })
 }
 fn lifetimes_to_generics(lifetimes: BoundLifetimes) -> Generics {